@@ -1,4 +1,4 @@
-use sol_ctf_framework::ChallengeBuilder;
+use sol_ctf_framework::{serve, TcpChallengeBuilder};
 
 use solana_sdk::{
     pubkey::Pubkey,
@@ -10,12 +10,8 @@ use solana_program::system_program;
 
 use std::{
     fs,
-    io::Write,
     error::Error,
-    net::{
-        TcpListener,
-        TcpStream
-    },
+    time::Duration,
 };
 
 use moar_horse::{
@@ -24,26 +20,19 @@ use moar_horse::{
 
 #[tokio::main]  
 async fn main() -> Result<(), Box<dyn Error>> {
-    let listener = TcpListener::bind("0.0.0.0:5001")?;
-    loop {
-        let (stream, _) = listener.accept()?;
-        // move each socket to a Tokio task
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream).await {
-                eprintln!("handler error: {e}");
-            }
-        });
-    }
+    serve("0.0.0.0:5001", handle_connection).await?;
+    Ok(())
 }
 
-async fn handle_connection(mut socket: TcpStream) -> Result<(), Box<dyn Error>> {
-    let mut builder = ChallengeBuilder::try_from(socket.try_clone().unwrap()).unwrap();
+async fn handle_connection(mut builder: TcpChallengeBuilder) -> Result<(), Box<dyn Error>> {
+    builder.set_read_timeout(Duration::from_secs(60));
+    builder.set_session_timeout(Duration::from_secs(300));
 
     // load programs
-    let solve_pubkey = match builder.input_program() {
+    let solve_pubkey = match builder.input_program().await {
         Ok(pubkey) => pubkey,
         Err(e) => {
-            writeln!(socket, "Error: cannot add solve program → {e}")?;
+            builder.write_line(&format!("Error: cannot add solve program → {e}")).await?;
             return Ok(());
         }
     };
@@ -53,8 +42,8 @@ async fn handle_connection(mut socket: TcpStream) -> Result<(), Box<dyn Error>>
     // make user
     let user = Keypair::new();
 
-    writeln!(socket, "program: {}", program_pubkey)?;
-    writeln!(socket, "user: {}", user.pubkey())?;
+    builder.write_line(&format!("program: {}", program_pubkey)).await?;
+    builder.write_line(&format!("user: {}", user.pubkey())).await?;
 
 
     // add accounts and lamports
@@ -85,7 +74,7 @@ async fn handle_connection(mut socket: TcpStream) -> Result<(), Box<dyn Error>>
     ).await?;
 
     // run solve
-    let ixs = challenge.read_instruction(solve_pubkey).unwrap();
+    let ixs = challenge.read_instruction(solve_pubkey).await?;
     challenge.run_ixs_full(
         &[ixs],
         &[&user],
@@ -94,11 +83,11 @@ async fn handle_connection(mut socket: TcpStream) -> Result<(), Box<dyn Error>>
 
     // check solve
     let balance = challenge.ctx.banks_client.get_account(user.pubkey()).await?.unwrap().lamports;
-    writeln!(socket, "lamports: {:?}", balance)?;
+    challenge.write_line(&format!("lamports: {:?}", balance)).await?;
 
     if balance > TARGET_AMT {
         let flag = fs::read_to_string("flag.txt").unwrap();
-        writeln!(socket, "hhhhhhhoooooooooooorrrrrrrrrrrrrrrssssssssssssssssseeeeeeeeeeeeeeeeeee\nFlag: {}", flag)?;
+        challenge.write_line(&format!("hhhhhhhoooooooooooorrrrrrrrrrrrrrrssssssssssssssssseeeeeeeeeeeeeeeeeee\nFlag: {}", flag)).await?;
     }
 
     Ok(())
@@ -1,46 +1,39 @@
+use solana_sdk::account::Account;
 use solana_sdk::signature::Keypair;
 use solana_sdk::signature::Signer;
-use sol_ctf_framework::ChallengeBuilder;
+use sol_ctf_framework::{serve, LogPolicy, TcpChallengeBuilder};
 use solana_program::pubkey::Pubkey;
 use solana_program::system_program;
 use std::env;
-use std::io::Write;
-use std::{
-    error::Error,
-    net::{TcpListener, TcpStream},
-};
-use threadpool::ThreadPool;
+use std::error::Error;
+use std::time::Duration;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let listener = TcpListener::bind("0.0.0.0:8080")?;
-    let pool = ThreadPool::new(4);
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-
-        pool.execute(|| {
-            handle_connection(stream).unwrap();
-        });
-    }
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    serve("0.0.0.0:8080", handle_connection).await?;
     Ok(())
 }
 
-fn handle_connection(mut socket: TcpStream) -> Result<(), Box<dyn Error>> {
-    let mut builder = ChallengeBuilder::try_from(socket.try_clone().unwrap()).unwrap();
+async fn handle_connection(mut builder: TcpChallengeBuilder) -> Result<(), Box<dyn Error>> {
+    builder.set_read_timeout(Duration::from_secs(60));
+    builder.set_session_timeout(Duration::from_secs(300));
 
-    let solve_pubkey = match builder.input_program() {
+    let solve_pubkey = match builder.input_program().await {
         Ok(pubkey) => pubkey,
         Err(e) => {
-            writeln!(socket, "Error: cannot add solve program → {e}")?;
+            builder.write_line(&format!("Error: cannot add solve program → {e}")).await?;
             return Ok(());
         }
     };
-    let program_pubkey = builder.chall_programs(&["./examples/solfire/solfire.so"])[0];
+    let program_pubkey = builder
+        .add_program("./examples/solfire/solfire.so", None)
+        .ok_or("Duplicate pubkey supplied")?;
 
     let user = Keypair::new();
 
-    writeln!(socket, "program pubkey: {}", program_pubkey)?;
-    writeln!(socket, "solve pubkey: {}", solve_pubkey)?;
-    writeln!(socket, "user pubkey: {}", user.pubkey())?;
+    builder.write_line(&format!("program pubkey: {}", program_pubkey)).await?;
+    builder.write_line(&format!("solve pubkey: {}", solve_pubkey)).await?;
+    builder.write_line(&format!("user pubkey: {}", user.pubkey())).await?;
 
     let (vault, _) = Pubkey::find_program_address(&["vault".as_ref()], &program_pubkey);
 
@@ -50,30 +43,29 @@ fn handle_connection(mut socket: TcpStream) -> Result<(), Box<dyn Error>> {
 
     builder
         .builder
-        .add_account_with_lamports(user.pubkey(), system_program::ID, INIT_BAL);
+        .add_account(user.pubkey(), Account::new(INIT_BAL, 0, &system_program::ID));
     builder
         .builder
-        .add_account_with_lamports(vault, system_program::ID, VAULT_BAL);
+        .add_account(vault, Account::new(VAULT_BAL, 0, &system_program::ID));
 
-    let mut challenge = builder.build();
+    let mut challenge = builder.build().await;
+    challenge.set_log_policy(LogPolicy::Program(solve_pubkey));
 
-    challenge.input_instruction(solve_pubkey, &[&user]).unwrap();
+    let ix = challenge.read_instruction(solve_pubkey).await?;
+    challenge.run_player_ixs(&[ix], &[&user], &user.pubkey()).await?;
 
-    let balance = challenge.env.get_account(user.pubkey()).unwrap().lamports;
+    let balance = challenge.ctx.banks_client.get_account(user.pubkey()).await?.unwrap().lamports;
+    let vault_balance = challenge.ctx.banks_client.get_account(vault).await?.unwrap().lamports;
 
-    writeln!(socket, "user bal: {:?}", balance)?;
-    writeln!(
-        socket,
-        "vault bal: {:?}",
-        challenge.env.get_account(vault).unwrap().lamports
-    )?;
+    challenge.write_line(&format!("user bal: {:?}", balance)).await?;
+    challenge.write_line(&format!("vault bal: {:?}", vault_balance)).await?;
 
     if balance > TARGET_AMT {
-        writeln!(socket, "congrats!")?;
+        challenge.write_line("congrats!").await?;
         if let Ok(flag) = env::var("FLAG") {
-            writeln!(socket, "flag: {:?}", flag)?;
+            challenge.write_line(&format!("flag: {:?}", flag)).await?;
         } else {
-            writeln!(socket, "flag not found, please contact admin")?;
+            challenge.write_line("flag not found, please contact admin").await?;
         }
     }
 
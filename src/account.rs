@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount, WritableAccount},
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{Challenge, ChallengeError};
+
+/// Account data layout understood by `Challenge::read_account` and `Challenge::write_account`
+///
+/// Implemented by the `Packed`, `Borsh` and `Anchor` wrappers, which pick the encoding
+pub trait AccountData: Sized {
+    fn decode(data: &[u8]) -> Result<Self, ChallengeError>;
+    fn encode(&self) -> Result<Vec<u8>, ChallengeError>;
+}
+
+/// Account stored with `Pack`, like the SPL Token states
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packed<T>(pub T);
+
+/// Account stored as plain Borsh, trailing bytes are ignored
+#[derive(Clone, Debug, PartialEq)]
+pub struct Borsh<T>(pub T);
+
+/// Anchor `#[account]`, with its discriminator checked on reads
+#[cfg(feature = "anchor")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anchor<T>(pub T);
+
+impl<T: Pack + IsInitialized> AccountData for Packed<T> {
+    fn decode(data: &[u8]) -> Result<Self, ChallengeError> {
+        T::unpack(data)
+            .map(Packed)
+            .map_err(|e| ChallengeError::InvalidAccountData(e.to_string()))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, ChallengeError> {
+        let mut data = vec![0; T::LEN];
+        self.0.pack_into_slice(&mut data);
+        Ok(data)
+    }
+}
+
+impl<T: BorshDeserialize + BorshSerialize> AccountData for Borsh<T> {
+    fn decode(mut data: &[u8]) -> Result<Self, ChallengeError> {
+        T::deserialize(&mut data)
+            .map(Borsh)
+            .map_err(|e| ChallengeError::InvalidAccountData(e.to_string()))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, ChallengeError> {
+        borsh::to_vec(&self.0).map_err(|e| ChallengeError::InvalidAccountData(e.to_string()))
+    }
+}
+
+#[cfg(feature = "anchor")]
+impl<T: anchor_lang::AccountDeserialize + anchor_lang::AccountSerialize> AccountData for Anchor<T> {
+    fn decode(mut data: &[u8]) -> Result<Self, ChallengeError> {
+        T::try_deserialize(&mut data)
+            .map(Anchor)
+            .map_err(|e| ChallengeError::InvalidAccountData(e.to_string()))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, ChallengeError> {
+        let mut data = vec![];
+        self.0
+            .try_serialize(&mut data)
+            .map_err(|e| ChallengeError::InvalidAccountData(e.to_string()))?;
+        Ok(data)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Reads and decodes an account, e.g. `read_account::<Borsh<Vault>>(vault)`
+    pub async fn read_account<T: AccountData>(&mut self, pubkey: Pubkey) -> Result<T, ChallengeError> {
+        T::decode(&self.get_account(&pubkey).await?.data)
+    }
+
+    /// Overwrites the data of an existing account, keeping its owner
+    ///
+    /// If the data grows, lamports are topped up so the account stays rent exempt
+    pub async fn write_account<T: AccountData>(&mut self, pubkey: Pubkey, value: &T) -> Result<(), ChallengeError> {
+        let data = value.encode()?;
+        let mut account = AccountSharedData::from(self.get_account(&pubkey).await?);
+
+        let minimum_balance = self.ctx.banks_client.get_rent().await?.minimum_balance(data.len());
+        if account.lamports() < minimum_balance {
+            account.set_lamports(minimum_balance);
+        }
+        account.set_data_from_slice(&data);
+
+        self.store_account(pubkey, &account).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use solana_sdk::clock::{Clock, Epoch, Slot, UnixTimestamp};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{Challenge, ChallengeError};
+
+/// Clock movement between player rounds, see `Challenge::set_round_advance`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RoundAdvance {
+    pub slots: u64,
+    pub seconds: i64,
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Reads the current `Clock` sysvar
+    pub async fn get_clock(&mut self) -> Result<Clock, ChallengeError> {
+        Ok(self.ctx.banks_client.get_sysvar::<Clock>().await?)
+    }
+
+    /// Moves the bank forward to `slot`, which has to be past the current one
+    pub async fn warp_to_slot(&mut self, slot: Slot) -> Result<(), ChallengeError> {
+        self.ctx
+            .warp_to_slot(slot)
+            .map_err(|e| ChallengeError::Setup(format!("cannot warp to slot {}: {}", slot, e)))
+    }
+
+    /// Moves the bank forward to the first slot of `epoch`
+    pub async fn warp_to_epoch(&mut self, epoch: Epoch) -> Result<(), ChallengeError> {
+        self.ctx
+            .warp_to_epoch(epoch)
+            .map_err(|e| ChallengeError::Setup(format!("cannot warp to epoch {}: {}", epoch, e)))
+    }
+
+    /// Overwrites the clock's unix timestamp, leaving slot and epoch alone
+    pub async fn set_unix_timestamp(&mut self, timestamp: UnixTimestamp) -> Result<(), ChallengeError> {
+        let mut clock = self.get_clock().await?;
+        clock.unix_timestamp = timestamp;
+        self.ctx.set_sysvar(&clock);
+
+        Ok(())
+    }
+
+    /// Moves the clock's unix timestamp forward by `seconds`
+    pub async fn advance_seconds(&mut self, seconds: i64) -> Result<(), ChallengeError> {
+        let timestamp = self.get_clock().await?.unix_timestamp;
+        self.set_unix_timestamp(timestamp.saturating_add(seconds)).await
+    }
+
+    /// Moves the clock by `advance` after every player transaction, failed ones included
+    ///
+    /// Applies to `run_player_ixs`, `run_player_ixs_v0` and `run_transaction`, letting time pass
+    /// between rounds
+    pub fn set_round_advance(&mut self, advance: RoundAdvance) {
+        self.round_advance = advance;
+    }
+
+    pub(crate) async fn end_round(&mut self) -> Result<(), ChallengeError> {
+        let RoundAdvance { slots, seconds } = self.round_advance;
+
+        if slots > 0 {
+            let slot = self.get_clock().await?.slot;
+            self.warp_to_slot(slot + slots).await?;
+        }
+        if seconds != 0 {
+            self.advance_seconds(seconds).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::Engine;
+use serde::Deserialize;
+
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{ChallengeBuilder, ChallengeError};
+
+/// Output of `solana account --output json`
+#[derive(Deserialize)]
+struct AccountDump {
+    pubkey: String,
+    account: DumpedAccount,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DumpedAccount {
+    lamports: u64,
+    /// `[data, encoding]`
+    data: (String, String),
+    owner: String,
+    executable: bool,
+    #[serde(default)]
+    rent_epoch: u64,
+}
+
+impl AccountDump {
+    fn into_account(self) -> Result<(Pubkey, Account), String> {
+        let (data, encoding) = self.account.data;
+        let data = match encoding.as_str() {
+            "base64" => base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| e.to_string())?,
+            encoding => return Err(format!("unsupported encoding {}", encoding)),
+        };
+
+        let account = Account {
+            lamports: self.account.lamports,
+            data,
+            owner: Pubkey::from_str(&self.account.owner).map_err(|e| e.to_string())?,
+            executable: self.account.executable,
+            rent_epoch: self.account.rent_epoch,
+        };
+
+        Ok((Pubkey::from_str(&self.pubkey).map_err(|e| e.to_string())?, account))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> ChallengeBuilder<R, W> {
+    /// Adds the account in a `solana account --output json` dump to genesis
+    pub fn add_account_from_json(&mut self, path: impl AsRef<Path>) -> Result<Pubkey, ChallengeError> {
+        let path = path.as_ref();
+        let invalid = |e: String| ChallengeError::Setup(format!("invalid account dump {}: {}", path.display(), e));
+
+        let json = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let dump: AccountDump = serde_json::from_str(&json).map_err(|e| invalid(e.to_string()))?;
+        let (pubkey, account) = dump.into_account().map_err(invalid)?;
+
+        self.builder.add_account(pubkey, account);
+
+        Ok(pubkey)
+    }
+
+    /// Adds every `*.json` account dump in `dir` to genesis, in file name order
+    pub fn add_accounts_from_dir(&mut self, dir: impl AsRef<Path>) -> Result<Vec<Pubkey>, ChallengeError> {
+        let dir = dir.as_ref();
+        let unreadable = |e: std::io::Error| ChallengeError::Setup(format!("cannot read {}: {}", dir.display(), e));
+
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir).map_err(unreadable)? {
+            let path = entry.map_err(unreadable)?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        paths.iter().map(|path| self.add_account_from_json(path)).collect()
+    }
+}
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::sync::Arc;
+
+use solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1;
+use solana_program_runtime::{execution_budget::SVMTransactionExecutionBudget, invoke_context::InvokeContext};
+use solana_sbpf::{elf::Executable, verifier::RequisiteVerifier};
+use solana_svm_feature_set::SVMFeatureSet;
+
+use crate::ChallengeError;
+
+/// Default cap on uploaded programs, see `ChallengeBuilder::set_max_program_size`
+pub const DEFAULT_MAX_PROGRAM_SIZE: usize = 10_000_000;
+
+const EM_BPF: u16 = 247;
+const EM_SBPF: u16 = 263;
+
+/// Checks that `elf` is a program the bank would load
+///
+/// Runs the header checks and then the runtime's own ELF loader and bytecode verifier, as
+/// lenient as the bank is when loading `bpf_loader` accounts, so old programs using e.g.
+/// `sol_alloc_free_` still pass. Every feature is treated as active, so challenges that
+/// deactivate features may see programs rejected that their bank would have loaded
+pub fn validate_program(elf: &[u8], max_size: usize) -> Result<(), ChallengeError> {
+    let invalid = |reason: String| ChallengeError::Protocol(format!("invalid program: {}", reason));
+
+    if elf.len() > max_size {
+        return Err(invalid(format!("larger than {} bytes", max_size)));
+    }
+    if elf.len() < 64 || elf[..4] != *b"\x7fELF" {
+        return Err(invalid("not an ELF file".into()));
+    }
+    if elf[4] != 2 || elf[5] != 1 {
+        return Err(invalid("not a 64-bit little-endian ELF".into()));
+    }
+    let machine = u16::from_le_bytes([elf[18], elf[19]]);
+    if machine != EM_BPF && machine != EM_SBPF {
+        return Err(invalid(format!("not an SBF program (machine {})", machine)));
+    }
+
+    let environment = create_program_runtime_environment_v1(
+        &SVMFeatureSet::all_enabled(),
+        &SVMTransactionExecutionBudget::default(),
+        false,
+        false,
+    )
+    .map_err(|e| ChallengeError::Setup(format!("cannot create program environment: {}", e)))?;
+
+    let executable = Executable::<InvokeContext>::load(elf, Arc::new(environment)).map_err(|e| invalid(e.to_string()))?;
+    executable
+        .verify::<RequisiteVerifier>()
+        .map_err(|e| invalid(e.to_string()))
+}
@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::io;
+use std::num::ParseIntError;
+
+use solana_program_test::BanksClientError;
+use solana_sdk::program_error::ProgramError;
+use solana_sdk::pubkey::{ParsePubkeyError, Pubkey};
+use solana_sdk::transaction::TransactionError;
+use thiserror::Error;
+
+/// Errors returned by `ChallengeBuilder` and `Challenge`
+#[derive(Error, Debug)]
+pub enum ChallengeError {
+    /// The player sent something that doesn't follow the protocol
+    #[error("{0}")]
+    Protocol(String),
+
+    /// The player took too long to send or receive data
+    #[error("{0}")]
+    Timeout(String),
+
+    /// Reading from or writing to the player, or the filesystem, failed
+    #[error(transparent)]
+    Io(io::Error),
+
+    /// A transaction was rejected or failed while executing
+    #[error("transaction failed: {error}")]
+    Transaction {
+        error: TransactionError,
+        /// Index of the failing instruction, if the failure is tied to one
+        instruction: Option<u8>,
+    },
+
+    /// The bank could not be reached
+    #[error(transparent)]
+    Banks(BanksClientError),
+
+    /// An account expected to exist in the bank is missing
+    #[error("account {0} not found")]
+    AccountNotFound(Pubkey),
+
+    /// Account data could not be decoded or encoded as the requested type
+    #[error("invalid account data: {0}")]
+    InvalidAccountData(String),
+
+    /// The challenge environment was configured incorrectly
+    #[error("setup error: {0}")]
+    Setup(String),
+}
+
+/// What players are told about failures that aren't theirs
+pub(crate) const INTERNAL_ERROR: &str = "internal error, please contact admin";
+
+impl ChallengeError {
+    /// Whether this error was caused by the player rather than the challenge itself
+    pub fn is_player_error(&self) -> bool {
+        matches!(
+            self,
+            ChallengeError::Protocol(_) | ChallengeError::Timeout(_) | ChallengeError::Transaction { .. }
+        )
+    }
+
+    /// Message safe to show to the player, hiding details of internal failures
+    pub fn player_message(&self) -> String {
+        if self.is_player_error() {
+            self.to_string()
+        } else {
+            INTERNAL_ERROR.to_string()
+        }
+    }
+}
+
+impl From<io::Error> for ChallengeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut => ChallengeError::Timeout(e.to_string()),
+            _ => ChallengeError::Io(e),
+        }
+    }
+}
+
+impl From<TransactionError> for ChallengeError {
+    fn from(error: TransactionError) -> Self {
+        let instruction = match error {
+            TransactionError::InstructionError(index, _) => Some(index),
+            _ => None,
+        };
+
+        ChallengeError::Transaction { error, instruction }
+    }
+}
+
+impl From<BanksClientError> for ChallengeError {
+    fn from(e: BanksClientError) -> Self {
+        match e {
+            BanksClientError::TransactionError(error)
+            | BanksClientError::SimulationError { err: error, .. } => error.into(),
+            e => ChallengeError::Banks(e),
+        }
+    }
+}
+
+impl From<ProgramError> for ChallengeError {
+    fn from(e: ProgramError) -> Self {
+        ChallengeError::Setup(e.to_string())
+    }
+}
+
+impl From<ParseIntError> for ChallengeError {
+    fn from(e: ParseIntError) -> Self {
+        ChallengeError::Protocol(e.to_string())
+    }
+}
+
+impl From<ParsePubkeyError> for ChallengeError {
+    fn from(e: ParsePubkeyError) -> Self {
+        ChallengeError::Protocol(e.to_string())
+    }
+}
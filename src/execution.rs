@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use solana_sdk::{
+    instruction::Instruction,
+    message::inner_instruction::InnerInstructionsList,
+    pubkey::Pubkey,
+    signature::Signer,
+    signer::signers::Signers,
+    transaction::{Transaction, TransactionError, VersionedTransaction},
+    transaction_context::TransactionReturnData,
+};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{Challenge, ChallengeError};
+
+/// What a processed transaction did, whether it succeeded or not
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutionMetadata {
+    pub result: Result<(), TransactionError>,
+    pub logs: Vec<String>,
+    pub compute_units_consumed: u64,
+    pub return_data: Option<TransactionReturnData>,
+    /// CPIs made by each top level instruction, with account indices into the transaction's keys
+    pub inner_instructions: InnerInstructionsList,
+}
+
+impl ExecutionMetadata {
+    /// The transaction outcome as a `ChallengeError::Transaction`, for use with `?`
+    pub fn check(&self) -> Result<(), ChallengeError> {
+        Ok(self.result.clone()?)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Like `run_ixs`, but returns logs, compute units, return data and CPIs
+    ///
+    /// A failed transaction is not an `Err`, see `ExecutionMetadata::result`
+    pub async fn run_ixs_with_metadata(&mut self, ixs: &[Instruction]) -> Result<ExecutionMetadata, ChallengeError> {
+        let payer = self.ctx.payer.insecure_clone();
+        self.run_ixs_full_with_metadata(ixs, &[&payer], &payer.pubkey()).await
+    }
+
+    /// Like `run_ix`, but returns logs, compute units, return data and CPIs
+    pub async fn run_ix_with_metadata(&mut self, ix: Instruction) -> Result<ExecutionMetadata, ChallengeError> {
+        self.run_ixs_with_metadata(&[ix]).await
+    }
+
+    /// Like `run_ixs_full`, but returns logs, compute units, return data and CPIs
+    pub async fn run_ixs_full_with_metadata<T: Signers + ?Sized>(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &T,
+        payer: &Pubkey,
+    ) -> Result<ExecutionMetadata, ChallengeError> {
+        let mut tx = Transaction::new_with_payer(ixs, Some(payer));
+        tx.try_sign(signers, self.ctx.last_blockhash)
+            .map_err(|e| ChallengeError::Protocol(format!("cannot sign transaction: {}", e)))?;
+
+        self.run_transaction_with_metadata(tx.into()).await
+    }
+
+    /// Like `run_transaction`, but returns logs, compute units, return data and CPIs
+    pub async fn run_transaction_with_metadata(
+        &mut self,
+        tx: VersionedTransaction,
+    ) -> Result<ExecutionMetadata, ChallengeError> {
+        self.execute(tx, None).await
+    }
+
+    /// Processes a transaction unless its simulation goes deeper than `max_cpi_depth`
+    pub(crate) async fn execute(
+        &mut self,
+        tx: VersionedTransaction,
+        max_cpi_depth: Option<usize>,
+    ) -> Result<ExecutionMetadata, ChallengeError> {
+        // inner instructions are only recorded when simulating, so simulate against the
+        // same bank state first and take everything else from the real run
+        self.track_transaction(&tx).await?;
+        let simulation = self.ctx.banks_client.simulate_transaction(tx.clone()).await?;
+        let inner_instructions = simulation
+            .simulation_details
+            .and_then(|details| details.inner_instructions)
+            .unwrap_or_default();
+
+        if let Some(max) = max_cpi_depth {
+            // stack height starts at 1 for top level instructions
+            let depth = inner_instructions
+                .iter()
+                .flatten()
+                .map(|ix| usize::from(ix.stack_height).saturating_sub(1))
+                .max()
+                .unwrap_or(0);
+            if depth > max {
+                return Err(ChallengeError::Protocol(format!("CPI depth above {}", max)));
+            }
+        }
+
+        let processed = self.ctx.banks_client.process_transaction_with_metadata(tx).await?;
+        let mut execution = ExecutionMetadata {
+            result: processed.result,
+            logs: vec![],
+            compute_units_consumed: 0,
+            return_data: None,
+            inner_instructions,
+        };
+        // missing when the transaction was rejected before executing
+        if let Some(metadata) = processed.metadata {
+            execution.logs = metadata.log_messages;
+            execution.compute_units_consumed = metadata.compute_units_consumed;
+            execution.return_data = metadata.return_data;
+        }
+
+        Ok(execution)
+    }
+}
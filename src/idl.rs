@@ -0,0 +1,257 @@
+// SPDX-License-Identifier: BSD-3-Clause
+//! Anchor IDL driven instruction encoding
+//!
+//! Only the Anchor 0.30+ IDL format is understood, older IDLs have to be converted
+//! with `anchor idl convert` first.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anchor_lang_idl_spec::{
+    IdlArrayLen, IdlDefinedFields, IdlInstruction, IdlInstructionAccount, IdlInstructionAccountItem,
+    IdlSerialization, IdlType, IdlTypeDef, IdlTypeDefTy,
+};
+use serde_json::{Map, Value};
+
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::ChallengeError;
+
+pub use anchor_lang_idl_spec::Idl;
+
+/// Loads an IDL JSON file, as written to `target/idl` by `anchor build`
+pub fn load_idl(path: impl AsRef<Path>) -> Result<Idl, ChallengeError> {
+    let json = std::fs::read_to_string(path.as_ref())
+        .map_err(|e| ChallengeError::Setup(format!("cannot read idl {}: {}", path.as_ref().display(), e)))?;
+
+    serde_json::from_str(&json).map_err(|e| ChallengeError::Setup(format!("invalid idl: {}", e)))
+}
+
+/// Builds the instruction `name` of `idl` from JSON encoded arguments and named accounts
+///
+/// `args` maps argument names to values, `accounts` maps account names to base58 pubkeys.
+/// Accounts with a fixed address in the IDL may be left out, as may optional accounts.
+pub fn encode_instruction(
+    idl: &Idl,
+    program_id: Pubkey,
+    name: &str,
+    args: &Map<String, Value>,
+    accounts: &Map<String, Value>,
+) -> Result<Instruction, ChallengeError> {
+    let ix = idl
+        .instructions
+        .iter()
+        .find(|ix| ix.name == name)
+        .ok_or_else(|| protocol(format!("unknown instruction {}", name)))?;
+
+    let mut data = ix.discriminator.clone();
+    let encoder = Encoder { types: &idl.types };
+    for arg in &ix.args {
+        let value = args
+            .get(&arg.name)
+            .ok_or_else(|| protocol(format!("missing arg {}", arg.name)))?;
+        encoder
+            .encode(&arg.ty, value, &mut data)
+            .map_err(|e| protocol(format!("arg {}: {}", arg.name, e)))?;
+    }
+    if let Some(extra) = args.keys().find(|key| !ix.args.iter().any(|arg| &arg.name == *key)) {
+        return Err(protocol(format!("unexpected arg {}", extra)));
+    }
+
+    let idl_accounts = flatten_accounts(ix);
+    if let Some(extra) = accounts.keys().find(|key| !idl_accounts.iter().any(|acc| &acc.name == *key)) {
+        return Err(protocol(format!("unexpected account {}", extra)));
+    }
+
+    let mut metas = vec![];
+    for acc in idl_accounts {
+        let pubkey = match (accounts.get(&acc.name), &acc.address) {
+            (Some(value), _) => {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| protocol(format!("account {} must be a string", acc.name)))?;
+                Pubkey::from_str(value)?
+            }
+            (None, Some(address)) => Pubkey::from_str(address)
+                .map_err(|e| ChallengeError::Setup(format!("invalid address in idl: {}", e)))?,
+            // anchor marks absent optional accounts with the program id
+            (None, None) if acc.optional => program_id,
+            (None, None) => return Err(protocol(format!("missing account {}", acc.name))),
+        };
+
+        metas.push(AccountMeta {
+            pubkey,
+            is_signer: acc.signer,
+            is_writable: acc.writable,
+        });
+    }
+
+    Ok(Instruction::new_with_bytes(program_id, &data, metas))
+}
+
+fn protocol(message: String) -> ChallengeError {
+    ChallengeError::Protocol(message)
+}
+
+fn flatten_accounts(ix: &IdlInstruction) -> Vec<&IdlInstructionAccount> {
+    fn walk<'a>(items: &'a [IdlInstructionAccountItem], out: &mut Vec<&'a IdlInstructionAccount>) {
+        for item in items {
+            match item {
+                IdlInstructionAccountItem::Single(acc) => out.push(acc),
+                IdlInstructionAccountItem::Composite(group) => walk(&group.accounts, out),
+            }
+        }
+    }
+
+    let mut out = vec![];
+    walk(&ix.accounts, &mut out);
+    out
+}
+
+/// Borsh encoder for JSON values, driven by IDL types
+struct Encoder<'a> {
+    types: &'a [IdlTypeDef],
+}
+
+impl Encoder<'_> {
+    fn encode(&self, ty: &IdlType, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+        match ty {
+            IdlType::Bool => out.push(value.as_bool().ok_or("expected bool")? as u8),
+            IdlType::U8 => out.extend(int::<u8>(value)?.to_le_bytes()),
+            IdlType::I8 => out.extend(int::<i8>(value)?.to_le_bytes()),
+            IdlType::U16 => out.extend(int::<u16>(value)?.to_le_bytes()),
+            IdlType::I16 => out.extend(int::<i16>(value)?.to_le_bytes()),
+            IdlType::U32 => out.extend(int::<u32>(value)?.to_le_bytes()),
+            IdlType::I32 => out.extend(int::<i32>(value)?.to_le_bytes()),
+            IdlType::U64 => out.extend(int::<u64>(value)?.to_le_bytes()),
+            IdlType::I64 => out.extend(int::<i64>(value)?.to_le_bytes()),
+            IdlType::U128 => out.extend(int::<u128>(value)?.to_le_bytes()),
+            IdlType::I128 => out.extend(int::<i128>(value)?.to_le_bytes()),
+            IdlType::F32 => out.extend((value.as_f64().ok_or("expected number")? as f32).to_le_bytes()),
+            IdlType::F64 => out.extend(value.as_f64().ok_or("expected number")?.to_le_bytes()),
+            IdlType::String => {
+                let s = value.as_str().ok_or("expected string")?;
+                out.extend((s.len() as u32).to_le_bytes());
+                out.extend(s.as_bytes());
+            }
+            IdlType::Bytes => {
+                let items = value.as_array().ok_or("expected array of bytes")?;
+                out.extend((items.len() as u32).to_le_bytes());
+                for item in items {
+                    out.push(int::<u8>(item)?);
+                }
+            }
+            IdlType::Pubkey => {
+                let s = value.as_str().ok_or("expected pubkey string")?;
+                out.extend(Pubkey::from_str(s).map_err(|e| e.to_string())?.to_bytes());
+            }
+            IdlType::Option(inner) => match value {
+                Value::Null => out.push(0),
+                value => {
+                    out.push(1);
+                    self.encode(inner, value, out)?;
+                }
+            },
+            IdlType::Vec(inner) => {
+                let items = value.as_array().ok_or("expected array")?;
+                out.extend((items.len() as u32).to_le_bytes());
+                for item in items {
+                    self.encode(inner, item, out)?;
+                }
+            }
+            IdlType::Array(inner, IdlArrayLen::Value(len)) => {
+                let items = value.as_array().ok_or("expected array")?;
+                if items.len() != *len {
+                    return Err(format!("expected {} elements", len));
+                }
+                for item in items {
+                    self.encode(inner, item, out)?;
+                }
+            }
+            IdlType::Defined { name, generics } if generics.is_empty() => {
+                let def = self
+                    .types
+                    .iter()
+                    .find(|def| &def.name == name)
+                    .ok_or_else(|| format!("unknown type {}", name))?;
+                self.encode_defined(def, value, out)?;
+            }
+            ty => return Err(format!("unsupported type {:?}", ty)),
+        }
+
+        Ok(())
+    }
+
+    fn encode_defined(&self, def: &IdlTypeDef, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+        if def.serialization != IdlSerialization::Borsh || !def.generics.is_empty() {
+            return Err(format!("unsupported type {}", def.name));
+        }
+
+        match &def.ty {
+            IdlTypeDefTy::Struct { fields } => self.encode_fields(fields.as_ref(), value, out),
+            IdlTypeDefTy::Type { alias } => self.encode(alias, value, out),
+            IdlTypeDefTy::Enum { variants } => {
+                // unit variants as "Name", others as {"Name": fields}
+                let (name, fields_value) = match value {
+                    Value::String(name) => (name, &Value::Null),
+                    Value::Object(map) if map.len() == 1 => map.iter().next().unwrap(),
+                    _ => return Err(format!("expected {} variant", def.name)),
+                };
+                let index = variants
+                    .iter()
+                    .position(|variant| &variant.name == name)
+                    .ok_or_else(|| format!("unknown variant {}", name))?;
+
+                out.push(index as u8);
+                self.encode_fields(variants[index].fields.as_ref(), fields_value, out)
+            }
+        }
+    }
+
+    fn encode_fields(&self, fields: Option<&IdlDefinedFields>, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+        match fields {
+            None => Ok(()),
+            Some(IdlDefinedFields::Named(fields)) => {
+                let map = value.as_object().ok_or("expected object")?;
+                for field in fields {
+                    let value = map.get(&field.name).ok_or_else(|| format!("missing field {}", field.name))?;
+                    self.encode(&field.ty, value, out)?;
+                }
+                Ok(())
+            }
+            Some(IdlDefinedFields::Tuple(types)) => {
+                let items = value.as_array().ok_or("expected array")?;
+                if items.len() != types.len() {
+                    return Err(format!("expected {} elements", types.len()));
+                }
+                for (ty, item) in types.iter().zip(items) {
+                    self.encode(ty, item, out)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Integers may be JSON numbers or strings, since u64 and wider don't survive every JSON encoder
+fn int<T: FromStr>(value: &Value) -> Result<T, String> {
+    let text = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return Err("expected integer".into()),
+    };
+
+    text.parse().map_err(|_| format!("invalid integer {}", text))
+}
+
+/// Parses a line of player input as a JSON object, `null` standing in for an empty one
+pub(crate) fn parse_object(line: &str, what: &str) -> Result<Map<String, Value>, ChallengeError> {
+    match serde_json::from_str(line) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(Value::Null) => Ok(Map::new()),
+        _ => Err(protocol(format!("{} must be a JSON object", what))),
+    }
+}
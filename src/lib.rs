@@ -1,67 +1,217 @@
 // SPDX-License-Identifier: BSD-3-Clause
-use std::fs::File;
-use std::error::Error;
 use std::str::FromStr;
-use std::net::TcpStream;
-use std::collections::HashSet;
-use std::io::{BufRead, BufReader, Write};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
+use tokio::io::{AsyncBufRead, AsyncWrite, BufReader, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+
+use solana_program_runtime::invoke_context::BuiltinFunctionWithContext;
 use solana_program_test::{ProgramTest, ProgramTestContext};
 
 use solana_sdk::signer::signers::Signers;
-use solana_sdk::{program_pack::Pack, transaction::Transaction};
+use solana_sdk::transaction::Transaction;
 use solana_sdk::{
+    account::Account,
+    bpf_loader,
     instruction::{AccountMeta, Instruction},
-    signature::{Keypair, Signer},
+    rent::Rent,
+    signature::Signer,
     pubkey::Pubkey,
 };
 
-use tempfile::Builder;
+use protocol::{JsonInstruction, JsonProgram, JsonProofOfWork};
+use transport::Transport;
+
+pub use account::{AccountData, Borsh, Packed};
+#[cfg(feature = "anchor")]
+pub use account::Anchor;
+pub use clock::RoundAdvance;
+pub use elf::{validate_program, DEFAULT_MAX_PROGRAM_SIZE};
+pub use error::ChallengeError;
+pub use execution::ExecutionMetadata;
+pub use limits::{InstructionLimits, TransactionLimits};
+pub use logs::LogPolicy;
+pub use protocol::Protocol;
+pub use solana_program_test::processor;
+pub use server::{serve, serve_listener, serve_with_limit, DEFAULT_MAX_SESSIONS};
+pub use snapshot::Snapshot;
+pub use token::{AccountExtension, CreatedMint, MintBuilder, MintExtension};
+pub use upgradeable::ProgramData;
+
+#[cfg(feature = "anchor")]
+pub mod idl;
+pub mod pow;
+pub mod protocol;
+
+mod account;
+mod clock;
+mod dump;
+mod elf;
+mod error;
+mod execution;
+mod limits;
+mod logs;
+mod lookup_table;
+mod player;
+mod server;
+mod snapshot;
+mod token;
+mod transport;
+mod upgradeable;
 
 mod helpers {
     use solana_sdk::signature::Keypair;
-    use rand::{prelude::StdRng, SeedableRng};
+    use rand::{prelude::StdRng, RngCore, SeedableRng};
     use sha2::{Digest, Sha256};
 
     pub fn keypair_from_data(data: &[u8]) -> Keypair {
         let mut hash = Sha256::default();
-        hash.update(&data);
+        hash.update(data);
 
         // panic here is probably fine since this should always be 32 bytes, regardless of user input
         let mut rng = StdRng::from_seed(hash.finalize()[..].try_into().unwrap());
-        Keypair::generate(&mut rng)
+
+        // same bytes `Keypair::generate` would draw, so derived addresses stay stable
+        let mut secret = [0u8; 32];
+        rng.fill_bytes(&mut secret);
+        Keypair::new_from_array(secret)
     }
 }
 
-pub struct Challenge<R: BufRead, W: Write> {
-    input: R,
-    output: W,
+pub struct Challenge<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> {
+    transport: Transport<R, W>,
     pub ctx: ProgramTestContext,
+    instruction_limits: InstructionLimits,
+    log_policy: LogPolicy,
+    transaction_limits: TransactionLimits,
+    round_advance: RoundAdvance,
+    touched: HashMap<Pubkey, Option<Account>>,
 }
 
-pub struct ChallengeBuilder<R: BufRead, W: Write> {
-    input: R,
-    output: W,
+pub struct ChallengeBuilder<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> {
+    transport: Transport<R, W>,
     pub builder: ProgramTest,
     added_programs: HashSet<Pubkey>,
+    pow_difficulty: Option<u32>,
+    transaction_limits: TransactionLimits,
+    instruction_limits: InstructionLimits,
+    max_program_size: usize,
+    native_programs: Vec<(&'static str, Pubkey, Option<BuiltinFunctionWithContext>)>,
+    prefer_native: bool,
 }
 
-impl<R: BufRead, W: Write> ChallengeBuilder<R, W> {
-
-    fn read_line(&mut self) -> Result<String, Box<dyn Error>> {
-        let mut line = String::new();
-        self.input.read_line(&mut line)?;
-
-        Ok(line.replace("\n", ""))
-    }
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> ChallengeBuilder<R, W> {
 
     /// Build challenge environment
-    pub async fn build(self) -> Challenge<R, W> {
+    pub async fn build(mut self) -> Challenge<R, W> {
+        // ProgramTest picks native or BPF when a program is added, so register them only now
+        // that `set_native_programs` can't change anymore
+        self.builder.prefer_bpf(!self.prefer_native);
+        for (name, key, processor) in self.native_programs {
+            self.builder.add_program(name, key, processor);
+        }
+
         Challenge {
-            input: self.input,
-            output: self.output,
+            transport: self.transport,
             ctx: self.builder.start_with_context().await,
+            instruction_limits: self.instruction_limits,
+            log_policy: LogPolicy::default(),
+            transaction_limits: self.transaction_limits,
+            round_advance: RoundAdvance::default(),
+            touched: HashMap::new(),
+        }
+    }
+
+    /// Writes a line to the player, as `{"message": line}` in JSON mode
+    pub async fn write_line(&mut self, line: &str) -> Result<(), ChallengeError> {
+        Ok(self.transport.write_message(line).await?)
+    }
+
+    /// Writes a named value to the player, as `name: value` or `{"name": "value"}`
+    pub async fn write_field(&mut self, name: &str, value: impl std::fmt::Display) -> Result<(), ChallengeError> {
+        Ok(self.transport.write_field(name, value).await?)
+    }
+
+    /// Selects the player protocol, carried over into the built `Challenge`
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.transport.set_protocol(protocol);
+    }
+
+    pub(crate) fn protocol_handle(&self) -> transport::ProtocolHandle {
+        self.transport.protocol_handle()
+    }
+
+    /// Limits how long the player may stay idle while we wait for input
+    ///
+    /// The timer restarts whenever data arrives, so slow uploads are fine as long as they keep
+    /// making progress. Reads stalling longer fail with `ChallengeError::Timeout`
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.transport.set_read_timeout(timeout);
+    }
+
+    /// Limits the whole session to `timeout` from now, carried over into the built `Challenge`
+    ///
+    /// Player I/O past the deadline fails with `ChallengeError::Timeout`
+    pub fn set_session_timeout(&mut self, timeout: Duration) {
+        self.transport.set_deadline(tokio::time::Instant::now() + timeout);
+    }
+
+    /// Requires the player to solve a proof of work with `difficulty` leading zero bits
+    /// before `input_program` accepts an upload
+    ///
+    /// See `pow::solve` for the matching client side solver
+    pub fn set_proof_of_work(&mut self, difficulty: u32) {
+        self.pow_difficulty = Some(difficulty);
+    }
+
+    /// Caps the compute units any single transaction may consume, setup included
+    pub fn set_compute_max_units(&mut self, units: u64) {
+        self.builder.set_compute_max_units(units);
+    }
+
+    /// Sets the compute, heap, account and CPI depth limits applied to player transactions,
+    /// i.e. those run with `Challenge::run_player_ixs`, `Challenge::run_player_ixs_v0` or
+    /// `Challenge::run_transaction`
+    pub fn set_transaction_limits(&mut self, limits: TransactionLimits) {
+        self.transaction_limits = limits;
+    }
+
+    /// Sets the limits on player instructions read with `Challenge::read_instruction` and
+    /// `Challenge::read_instructions`
+    pub fn set_instruction_limits(&mut self, limits: InstructionLimits) {
+        self.instruction_limits = limits;
+    }
+
+    /// Caps the size of programs accepted by `input_program`, `DEFAULT_MAX_PROGRAM_SIZE` by default
+    pub fn set_max_program_size(&mut self, max_size: usize) {
+        self.max_program_size = max_size;
+    }
+
+    /// Issues a fresh proof of work challenge and checks the player's answer
+    async fn check_proof_of_work(&mut self, difficulty: u32) -> Result<(), ChallengeError> {
+        let prefix = pow::new_prefix();
+
+        let solution = match self.transport.protocol() {
+            Protocol::Text => {
+                self.transport.write_field("pow prefix", &prefix).await?;
+                self.transport.write_field("pow difficulty", difficulty).await?;
+                self.transport.prompt("pow solution").await?;
+                self.transport.read_line().await?
+            }
+            Protocol::Json => {
+                self.transport
+                    .write_json(&serde_json::json!({ "prompt": "pow", "prefix": prefix, "difficulty": difficulty }))
+                    .await?;
+                self.transport.read_json::<JsonProofOfWork>().await?.solution
+            }
+        };
+
+        if !pow::verify(&prefix, solution.trim(), difficulty) {
+            return Err(ChallengeError::Protocol("Invalid proof of work".into()));
         }
+
+        Ok(())
     }
 
     /// Adds programs to challenge environment
@@ -69,176 +219,169 @@ impl<R: BufRead, W: Write> ChallengeBuilder<R, W> {
     /// Returns vector of program pubkeys, with positions corresponding to input slice
     pub fn add_program(&mut self, path: &str, key: Option<Pubkey>) -> Option<Pubkey> {
         let program_so = std::fs::read(path).unwrap();
-        let program_key = key.unwrap_or(helpers::keypair_from_data(&program_so).pubkey());
 
-        let name_owned = path.trim_end_matches(".so").to_owned();
-        let name_static: &'static str = Box::leak(name_owned.into_boxed_str());
+        self.add_program_bytes(&program_so, key)
+    }
+
+    /// Adds a program that can run either natively or from its `.so`, see `set_native_programs`
+    ///
+    /// `processor` is built with `processor!(process_instruction)`. In BPF mode `{name}.so` is
+    /// looked up in `BPF_OUT_DIR`, `tests/fixtures` or the working directory instead.
+    pub fn add_native_program(
+        &mut self,
+        name: &'static str,
+        key: Pubkey,
+        processor: Option<BuiltinFunctionWithContext>,
+    ) -> Option<Pubkey> {
+        // Prevent duplicate program IDs
+        if !self.added_programs.insert(key) {
+            return None;
+        }
+
+        self.native_programs.push((name, key, processor));
+
+        Some(key)
+    }
+
+    /// Runs programs added with `add_native_program` as native Rust instead of SBF, keeping
+    /// backtraces and breakpoints, e.g. `set_native_programs(cfg!(debug_assertions))`
+    ///
+    /// Applies to all of them regardless of call order, programs added from ELF bytes or paths
+    /// are unaffected
+    pub fn set_native_programs(&mut self, native: bool) {
+        self.prefer_native = native;
+    }
+
+    /// Adds a program from its ELF bytes, owned by the BPF loader like `add_program` does
+    ///
+    /// Without a key the address is derived from the ELF. Returns `None` for duplicate addresses
+    pub fn add_program_bytes(&mut self, program_so: &[u8], key: Option<Pubkey>) -> Option<Pubkey> {
+        let program_key = key.unwrap_or(helpers::keypair_from_data(program_so).pubkey());
 
         // Prevent duplicate program IDs
         if !self.added_programs.insert(program_key) {
             return None;
         }
 
-        self.builder.add_program(name_static, program_key, None);
+        self.builder.add_account(
+            program_key,
+            Account {
+                lamports: Rent::default().minimum_balance(program_so.len()).max(1),
+                data: program_so.to_vec(),
+                owner: bpf_loader::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        );
 
         Some(program_key)
     }
 
     /// Reads program from input and adds it to environment
-    pub fn input_program(&mut self) -> Result<Pubkey, Box<dyn Error>> {
-        writeln!(self.output, "program pubkey: ")?;
-        let program_key = Pubkey::from_str(&self.read_line()?)?;
+    ///
+    /// If a proof of work is configured, the player has to solve it before anything else is read.
+    /// Uploads are checked with `validate_program` before they reach the bank
+    pub async fn input_program(&mut self) -> Result<Pubkey, ChallengeError> {
+        if let Some(difficulty) = self.pow_difficulty {
+            self.check_proof_of_work(difficulty).await?;
+        }
 
-        writeln!(self.output, "program len: ")?;
-        let len: usize = std::cmp::min(10_000_000, self.read_line()?.parse()?);
+        let (program_key, input_so) = match self.transport.protocol() {
+            Protocol::Text => {
+                self.transport.prompt("program pubkey").await?;
+                let program_key = Pubkey::from_str(&self.transport.read_line().await?)?;
 
-        let mut input_so = vec![0; len];
-        self.input.read_exact(&mut input_so)?;
+                self.transport.prompt("program len").await?;
+                let len: usize = self.transport.read_line().await?.trim().parse()?;
+                if len > self.max_program_size {
+                    return Err(ChallengeError::Protocol(format!(
+                        "invalid program: larger than {} bytes",
+                        self.max_program_size
+                    )));
+                }
 
-        let dir = Builder::new()
-            .prefix("my-temporary-dir")
-            .rand_bytes(5)
-            .tempdir()?;
+                let mut input_so = vec![0; len];
+                self.transport.read_exact(&mut input_so).await?;
 
-        let file_path = dir.path().join("solve.so");
-        let mut input_file = File::create(file_path.clone())?;
+                (program_key, input_so)
+            }
+            Protocol::Json => {
+                // base64 of the largest accepted program, plus room for the pubkey and field names
+                let max_line_len = self.max_program_size.div_ceil(3) * 4 + 256;
+
+                self.transport.prompt("program").await?;
+                self.transport.read_json_limited::<JsonProgram>(max_line_len).await?.decode()?
+            }
+        };
 
-        input_file.write_all(&input_so)?;
+        validate_program(&input_so, self.max_program_size)?;
 
-        match self.add_program(&file_path.to_str().unwrap(), Some(program_key)) {
-            Some(program_key) => Ok(program_key),  
-            None => Err("Duplicate pubkey supplied".into()),        
-        }   
+        match self.add_program_bytes(&input_so, Some(program_key)) {
+            Some(program_key) => Ok(program_key),
+            None => Err(ChallengeError::Protocol("Duplicate pubkey supplied".into())),
+        }
     }
 }
 
-impl<R: BufRead, W: Write> Challenge<R, W> {
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
     pub fn builder(input: R, output: W) -> ChallengeBuilder<R, W> {
         let mut builder = ProgramTest::default();
         builder.prefer_bpf(true);
 
         ChallengeBuilder {
-            input,
-            output,
+            transport: Transport::new(input, output),
             builder,
             added_programs: HashSet::new(),
+            pow_difficulty: None,
+            transaction_limits: TransactionLimits::default(),
+            instruction_limits: InstructionLimits::default(),
+            max_program_size: DEFAULT_MAX_PROGRAM_SIZE,
+            native_programs: vec![],
+            prefer_native: false,
         }
     }
 
-    pub async fn add_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Result<Pubkey, Box<dyn Error>> {
-    let token_account_keypair = Keypair::new();
-    let token_account = token_account_keypair.pubkey();
-    let payer = &self.ctx.payer;
-    let mut tx = Transaction::new_with_payer(
-        &[
-            solana_program::system_instruction::create_account(
-                &payer.pubkey(),
-                &token_account,
-                10000000,
-                spl_token::state::Account::LEN.try_into().unwrap(),
-                &spl_token::ID,
-            ),
-            spl_token::instruction::initialize_account(
-                &spl_token::ID,
-                &token_account,
-                &mint,
-                &owner
-            )?,
-        ],
-        Some(&payer.pubkey()),
-    );
-    tx.sign(&[&token_account_keypair, payer], self.ctx.last_blockhash);
-    self.ctx.banks_client
-        .process_transaction_with_preflight(tx)
-        .await?;
-
-    Ok(token_account)
-}
+    /// Writes a line to the player, as `{"message": line}` in JSON mode
+    pub async fn write_line(&mut self, line: &str) -> Result<(), ChallengeError> {
+        Ok(self.transport.write_message(line).await?)
+    }
 
-pub async fn add_mint(&mut self) -> Result<Pubkey, Box<dyn Error>> {
-    let mint_keypair = Keypair::new();
-    let mint = mint_keypair.pubkey();
-    let payer = &self.ctx.payer;
-    let mut tx = Transaction::new_with_payer(
-        &[
-            solana_program::system_instruction::create_account(
-                &payer.pubkey(),
-                &mint,
-                10000000,
-                spl_token::state::Mint::LEN.try_into().unwrap(),
-                &spl_token::ID,
-            ),
-            spl_token::instruction::initialize_mint(
-                &spl_token::ID,
-                &mint,
-                &payer.pubkey(),
-                None,
-                9,
-            )?,
-        ],
-        Some(&payer.pubkey()),
-    );
-    tx.sign(&[&mint_keypair, payer], self.ctx.last_blockhash);
-    self.ctx.banks_client
-        .process_transaction_with_preflight(tx)
-        .await?;
-
-    Ok(mint)
-}
+    /// Writes a named value to the player, as `name: value` or `{"name": "value"}`
+    pub async fn write_field(&mut self, name: &str, value: impl std::fmt::Display) -> Result<(), ChallengeError> {
+        Ok(self.transport.write_field(name, value).await?)
+    }
 
-    pub async fn mint_to(
-        &mut self,
-    amount: u64,
-    mint: &Pubkey,
-    account: &Pubkey,
-) -> Result<(), Box<dyn Error>> {
-    self.run_ix(
-        spl_token::instruction::mint_to(
-            &spl_token::ID,
-            mint,
-            account,
-            &self.ctx.payer.pubkey(),
-            &[],
-            amount,
-        )?,
-    )
-    .await
-}
+    pub(crate) async fn get_account(&mut self, pubkey: &Pubkey) -> Result<Account, ChallengeError> {
+        self.ctx.banks_client
+            .get_account(*pubkey)
+            .await?
+            .ok_or(ChallengeError::AccountNotFound(*pubkey))
+    }
 
-    pub async fn run_ixs(&mut self, ixs: &[Instruction]) -> Result<(), Box<dyn Error>> {
+    pub async fn run_ixs(&mut self, ixs: &[Instruction]) -> Result<(), ChallengeError> {
         let payer_keypair = &self.ctx.payer;
         let payer = payer_keypair.pubkey();
         let mut tx = Transaction::new_with_payer(ixs, Some(&payer));
 
         tx.sign(&[payer_keypair], self.ctx.last_blockhash);
-        self.ctx.banks_client
-            .process_transaction_with_preflight(tx)
-            .await?;
+        self.process_transaction(tx).await?;
 
         Ok(())
     }
 
-    pub async fn run_ix(&mut self, ix: Instruction) -> Result<(), Box<dyn Error>> {
+    pub async fn run_ix(&mut self, ix: Instruction) -> Result<(), ChallengeError> {
         self.run_ixs(&[ix]).await
     }
 
-    pub async fn run_ixs_full<T: Signers>(&mut self, ixs: &[Instruction], signers: &T, payer: &Pubkey) -> Result<(), Box<dyn Error>> {
+    pub async fn run_ixs_full<T: Signers>(&mut self, ixs: &[Instruction], signers: &T, payer: &Pubkey) -> Result<(), ChallengeError> {
     let mut tx = Transaction::new_with_payer(ixs, Some(payer));
 
     tx.sign(signers, self.ctx.last_blockhash);
-    self.ctx.banks_client
-        .process_transaction_with_preflight(tx)
-        .await?;
+    self.process_transaction(tx).await?;
 
     Ok(())
 }
 
-pub async fn read_token_account(&mut self, pubkey: Pubkey) -> Result<spl_token::state::Account, Box<dyn Error>> {
-    Ok(spl_token::state::Account::unpack(
-        &self.ctx.banks_client.get_account(pubkey).await?.unwrap().data
-    )?)
-}
-
     /// Reads instruction accounts/data from input and sends in transaction to specified program
     ///
     /// # Account Format:
@@ -246,23 +389,110 @@ pub async fn read_token_account(&mut self, pubkey: Pubkey) -> Result<spl_token::
     ///
     /// `[meta]` - contains "s" if account is a signer, "w" if it is writable
     /// `[pubkey]` - the address of the account
-    pub fn read_instruction(
+    ///
+    /// In JSON mode a single `protocol::JsonInstruction` is read instead
+    ///
+    /// Fails if the instruction exceeds the challenge's `InstructionLimits`
+    pub async fn read_instruction(
+        &mut self,
+        program_id: Pubkey,
+    ) -> Result<Instruction, ChallengeError> {
+        let limits = self.instruction_limits;
+
+        if self.transport.protocol() == Protocol::Json {
+            self.transport.prompt("instruction").await?;
+            let ix = self
+                .transport
+                .read_json_limited::<JsonInstruction>(limits.max_json_len())
+                .await?
+                .into_instruction(Some(program_id))?;
+
+            limits.check_accounts(ix.accounts.len())?;
+            if ix.data.len() > limits.max_data_len {
+                return Err(ChallengeError::Protocol("instruction data too large".into()));
+            }
+
+            return Ok(ix);
+        }
+
+        self.read_text_instruction(program_id, limits.max_data_len).await
+    }
+
+    /// Reads a batch of instructions, each for a program of the player's choosing, to be sent
+    /// as one transaction
+    ///
+    /// # Input Format:
+    /// `num instructions: ` followed by, per instruction, `program id: ` and the
+    /// `read_instruction` format
+    ///
+    /// In JSON mode a single array of `protocol::JsonInstruction` with `program_id` set is read
+    ///
+    /// Fails if the batch exceeds the challenge's `InstructionLimits`
+    pub async fn read_instructions(&mut self) -> Result<Vec<Instruction>, ChallengeError> {
+        let limits = self.instruction_limits;
+        let too_many = || ChallengeError::Protocol(format!("at most {} instructions allowed", limits.max_instructions));
+        let too_large = || ChallengeError::Protocol(format!("at most {} bytes of instruction data allowed", limits.max_data_len));
+
+        if self.transport.protocol() == Protocol::Json {
+            self.transport.prompt("instructions").await?;
+            let ixs = self
+                .transport
+                .read_json_limited::<Vec<JsonInstruction>>(limits.max_json_len())
+                .await?
+                .into_iter()
+                .map(|ix| ix.into_instruction(None))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if ixs.len() > limits.max_instructions {
+                return Err(too_many());
+            }
+            for ix in &ixs {
+                limits.check_accounts(ix.accounts.len())?;
+            }
+            if ixs.iter().map(|ix| ix.data.len()).sum::<usize>() > limits.max_data_len {
+                return Err(too_large());
+            }
+
+            return Ok(ixs);
+        }
+
+        self.transport.prompt("num instructions").await?;
+        let num_ixs: usize = self.transport.read_line().await?.trim().parse()?;
+        if num_ixs > limits.max_instructions {
+            return Err(too_many());
+        }
+
+        let mut ixs = Vec::with_capacity(num_ixs);
+        let mut data_left = limits.max_data_len;
+        for _ in 0..num_ixs {
+            self.transport.prompt("program id").await?;
+            let program_id = Pubkey::from_str(self.transport.read_line().await?.trim())?;
+
+            let ix = self.read_text_instruction(program_id, data_left).await?;
+            data_left -= ix.data.len();
+            ixs.push(ix);
+        }
+
+        Ok(ixs)
+    }
+
+    /// Reads one instruction in the text format, refusing more than `max_data_len` bytes of data
+    async fn read_text_instruction(
         &mut self,
         program_id: Pubkey,
-    ) -> Result<Instruction, Box<dyn Error>> {
-        let mut line = String::new();
-        writeln!(self.output, "num accounts: ")?;
-        self.input.read_line(&mut line)?;
-        let num_accounts: usize = line.trim().parse()?;
+        max_data_len: usize,
+    ) -> Result<Instruction, ChallengeError> {
+        self.transport.prompt("num accounts").await?;
+        let num_accounts: usize = self.transport.read_line().await?.trim().parse()?;
+        self.instruction_limits.check_accounts(num_accounts)?;
 
-        let mut metas = vec![];
+        let mut metas = Vec::with_capacity(num_accounts);
         for _ in 0..num_accounts {
-            line.clear();
-            self.input.read_line(&mut line)?;
+            let line = self.transport.read_line().await?;
 
             let mut it = line.trim().split(' ');
-            let meta = it.next().ok_or("bad meta")?;
-            let pubkey = it.next().ok_or("bad pubkey")?;
+            let meta = it.next().ok_or_else(|| ChallengeError::Protocol("bad meta".into()))?;
+            let pubkey = it.next().ok_or_else(|| ChallengeError::Protocol("bad pubkey".into()))?;
             let pubkey = Pubkey::from_str(pubkey)?;
 
             let is_signer = meta.contains('s');
@@ -275,25 +505,70 @@ pub async fn read_token_account(&mut self, pubkey: Pubkey) -> Result<spl_token::
             }
         }
 
-        line.clear();
-        writeln!(self.output, "ix len: ")?;
-        self.input.read_line(&mut line)?;
-        let ix_data_len: usize = line.trim().parse()?;
+        self.transport.prompt("ix len").await?;
+        let ix_data_len: usize = self.transport.read_line().await?.trim().parse()?;
+        if ix_data_len > max_data_len {
+            return Err(ChallengeError::Protocol("instruction data too large".into()));
+        }
         let mut ix_data = vec![0; ix_data_len];
 
-        self.input.read_exact(&mut ix_data)?;
+        self.transport.read_exact(&mut ix_data).await?;
 
         let ix = Instruction::new_with_bytes(program_id, &ix_data, metas);
 
         Ok(ix)
     }
+
+    /// Reads an Anchor instruction by name and encodes it using the program's IDL
+    ///
+    /// # Input Format:
+    /// `[name]` - the instruction name as it appears in the IDL
+    ///
+    /// `[args]` - JSON object mapping argument names to values
+    ///
+    /// `[accounts]` - JSON object mapping account names to base58 pubkeys
+    ///
+    /// In JSON mode all three come as one `{"name": .., "args": .., "accounts": ..}` object
+    ///
+    /// Signer and writable flags come from the IDL, see `idl::encode_instruction`
+    #[cfg(feature = "anchor")]
+    pub async fn read_anchor_instruction(
+        &mut self,
+        program_id: Pubkey,
+        idl: &idl::Idl,
+    ) -> Result<Instruction, ChallengeError> {
+        if self.transport.protocol() == Protocol::Json {
+            self.transport.prompt("anchor instruction").await?;
+            let ix = self.transport.read_json::<protocol::JsonAnchorInstruction>().await?;
+            return idl::encode_instruction(idl, program_id, &ix.name, &ix.args, &ix.accounts);
+        }
+
+        self.transport.prompt("instruction name").await?;
+        let name = self.transport.read_line().await?;
+
+        self.transport.prompt("args").await?;
+        let args = idl::parse_object(&self.transport.read_line().await?, "args")?;
+
+        self.transport.prompt("accounts").await?;
+        let accounts = idl::parse_object(&self.transport.read_line().await?, "accounts")?;
+
+        idl::encode_instruction(idl, program_id, name.trim(), &args, &accounts)
+    }
 }
 
-impl TryFrom<TcpStream> for ChallengeBuilder<BufReader<TcpStream>, TcpStream> {
+/// Builder for a player connected over TCP, as handed out by `serve`
+pub type TcpChallengeBuilder = ChallengeBuilder<BufReader<ReadHalf<TcpStream>>, WriteHalf<TcpStream>>;
+
+impl TryFrom<TcpStream> for TcpChallengeBuilder {
     type Error = std::io::Error;
 
     fn try_from(socket: TcpStream) -> Result<Self, Self::Error> {
-        let reader = BufReader::new(socket.try_clone()?);
-        Ok(Challenge::builder(reader, socket))
+        // prompts are small and interactive, don't let Nagle hold them back
+        socket.set_nodelay(true)?;
+
+        // unlike `into_split`, dropping these halves doesn't shut the socket down, so `serve`
+        // can still report a handler's error after the builder is gone
+        let (reader, writer) = tokio::io::split(socket);
+        Ok(Challenge::builder(BufReader::new(reader), writer))
     }
 }
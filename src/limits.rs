@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use borsh::BorshDeserialize;
+
+use solana_compute_budget_interface::{ComputeBudgetInstruction, ID as COMPUTE_BUDGET_ID};
+use solana_sdk::{
+    instruction::Instruction,
+    message::VersionedMessage,
+    packet::PACKET_DATA_SIZE,
+    transaction::{TransactionError, VersionedTransaction},
+};
+
+use crate::{ChallengeError, ExecutionMetadata};
+
+/// Limits applied to player transactions, see `ChallengeBuilder::set_transaction_limits`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransactionLimits {
+    /// Compute unit limit of player transactions, requested on the player's behalf unless
+    /// they set a lower one themselves. Player-signed transactions can't be amended, so they
+    /// have to request one at or below it, which needs `allow_compute_budget`
+    pub compute_units: Option<u32>,
+    /// Heap frame of player transactions in bytes, a multiple of 1024 up to 256 KiB
+    pub heap_size: Option<u32>,
+    /// Maximum number of accounts a player transaction may reference, programs included
+    pub max_accounts: Option<usize>,
+    /// Whether players may send ComputeBudget instructions of their own
+    pub allow_compute_budget: bool,
+    /// Maximum CPI depth, 1 letting the player's instructions invoke programs that can't invoke
+    /// any further. Checked by simulating first, deeper transactions are rejected unprocessed
+    pub max_cpi_depth: Option<usize>,
+}
+
+impl Default for TransactionLimits {
+    fn default() -> Self {
+        TransactionLimits {
+            compute_units: None,
+            heap_size: None,
+            max_accounts: None,
+            allow_compute_budget: true,
+            max_cpi_depth: None,
+        }
+    }
+}
+
+/// Caps on what `Challenge::read_instruction` and `Challenge::read_instructions` accept from
+/// the player, see `ChallengeBuilder::set_instruction_limits`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionLimits {
+    /// Maximum number of instructions
+    pub max_instructions: usize,
+    /// Maximum instruction data summed over all instructions, in bytes
+    pub max_data_len: usize,
+    /// Maximum number of account metas per instruction
+    pub max_accounts: usize,
+}
+
+impl Default for InstructionLimits {
+    fn default() -> Self {
+        InstructionLimits {
+            max_instructions: 16,
+            max_data_len: PACKET_DATA_SIZE,
+            max_accounts: 64,
+        }
+    }
+}
+
+impl InstructionLimits {
+    /// Longest JSON batch line that can still fit the limits: all data hex encoded, the widest
+    /// encoding, and per instruction room for a program id and `max_accounts` account metas
+    pub(crate) fn max_json_len(&self) -> usize {
+        self.max_data_len * 2 + self.max_instructions * (128 + self.max_accounts * 96)
+    }
+
+    pub(crate) fn check_accounts(&self, count: usize) -> Result<(), ChallengeError> {
+        if count > self.max_accounts {
+            return Err(ChallengeError::Protocol(format!(
+                "at most {} accounts per instruction allowed",
+                self.max_accounts
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// ComputeBudget settings a player transaction asked for itself
+#[derive(Default)]
+struct Requested {
+    compute_units: bool,
+    heap_size: bool,
+}
+
+impl TransactionLimits {
+    /// Checks player instructions and prepends the configured ComputeBudget instructions,
+    /// returning how many were prepended along with the instructions to run
+    pub(crate) fn apply(&self, ixs: &[Instruction]) -> Result<(Vec<Instruction>, usize), ChallengeError> {
+        let requested = self.check_compute_budget(
+            ixs.iter()
+                .filter(|ix| ix.program_id == COMPUTE_BUDGET_ID)
+                .map(|ix| ix.data.as_slice()),
+        )?;
+
+        let mut out = vec![];
+        match self.compute_units {
+            Some(units) if !requested.compute_units => out.push(ComputeBudgetInstruction::set_compute_unit_limit(units)),
+            _ => {}
+        }
+        match self.heap_size {
+            Some(bytes) if !requested.heap_size => out.push(ComputeBudgetInstruction::request_heap_frame(bytes)),
+            _ => {}
+        }
+        let prepended = out.len();
+        out.extend_from_slice(ixs);
+
+        Ok((out, prepended))
+    }
+
+    /// Checks a player transaction, which can't be amended once signed
+    pub(crate) fn check(&self, tx: &VersionedTransaction) -> Result<(), ChallengeError> {
+        let keys = tx.message.static_account_keys();
+        let requested = self.check_compute_budget(
+            tx.message
+                .instructions()
+                .iter()
+                .filter(|ix| keys.get(ix.program_id_index as usize) == Some(&COMPUTE_BUDGET_ID))
+                .map(|ix| ix.data.as_slice()),
+        )?;
+
+        // otherwise the runtime default of 200k units per instruction would apply
+        if let Some(max) = self.compute_units.filter(|_| !requested.compute_units) {
+            return Err(ChallengeError::Protocol(format!(
+                "transaction must set a compute unit limit of at most {}",
+                max
+            )));
+        }
+
+        self.check_accounts(account_count(&tx.message))
+    }
+
+    pub(crate) fn check_accounts(&self, count: usize) -> Result<(), ChallengeError> {
+        match self.max_accounts {
+            Some(max) if count > max => Err(ChallengeError::Protocol(format!(
+                "transaction references {} accounts, at most {} allowed",
+                count, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_compute_budget<'a>(&self, ixs: impl Iterator<Item = &'a [u8]>) -> Result<Requested, ChallengeError> {
+        let mut requested = Requested::default();
+
+        for data in ixs {
+            if !self.allow_compute_budget {
+                return Err(ChallengeError::Protocol("ComputeBudget instructions are not allowed".into()));
+            }
+
+            match ComputeBudgetInstruction::try_from_slice(data) {
+                Ok(ComputeBudgetInstruction::SetComputeUnitLimit(units)) => {
+                    if let Some(max) = self.compute_units.filter(|max| units > *max) {
+                        return Err(ChallengeError::Protocol(format!("compute unit limit above {}", max)));
+                    }
+                    requested.compute_units = true;
+                }
+                Ok(ComputeBudgetInstruction::RequestHeapFrame(bytes)) => {
+                    if let Some(max) = self.heap_size.filter(|max| bytes > *max) {
+                        return Err(ChallengeError::Protocol(format!("heap frame above {}", max)));
+                    }
+                    requested.heap_size = true;
+                }
+                // malformed ones are left for the runtime to reject
+                _ => {}
+            }
+        }
+
+        Ok(requested)
+    }
+}
+
+/// Number of accounts a message references, those loaded from lookup tables included
+pub(crate) fn account_count(message: &VersionedMessage) -> usize {
+    let looked_up: usize = message
+        .address_table_lookups()
+        .unwrap_or_default()
+        .iter()
+        .map(|lookup| lookup.writable_indexes.len() + lookup.readonly_indexes.len())
+        .sum();
+
+    message.static_account_keys().len() + looked_up
+}
+
+/// Makes an execution look as if the `prepended` ComputeBudget instructions `apply` added
+/// weren't there, so instruction indices and logs match what the player sent
+pub(crate) fn hide_prepended(execution: &mut ExecutionMetadata, prepended: usize) {
+    if prepended == 0 {
+        return;
+    }
+
+    if let Err(TransactionError::InstructionError(index, error)) = &execution.result {
+        if let Some(index) = index.checked_sub(prepended as u8) {
+            execution.result = Err(TransactionError::InstructionError(index, error.clone()));
+        }
+    }
+
+    let inner = prepended.min(execution.inner_instructions.len());
+    execution.inner_instructions.drain(..inner);
+
+    // each of them logs an invoke and a success line, being builtins that never CPI
+    let prefix = format!("Program {} ", COMPUTE_BUDGET_ID);
+    let lines = execution
+        .logs
+        .iter()
+        .take(prepended * 2)
+        .take_while(|line| line.starts_with(&prefix))
+        .count();
+    execution.logs.drain(..lines);
+}
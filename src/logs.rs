@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::str::FromStr;
+
+use serde_json::json;
+
+use solana_sdk::{
+    instruction::Instruction, message::Message, pubkey::Pubkey, signer::signers::Signers, transaction::Transaction,
+};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::limits::hide_prepended;
+use crate::{Challenge, ChallengeError, ExecutionMetadata, Protocol};
+
+/// Which program logs the player gets to see after `Challenge::run_player_ixs`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogPolicy {
+    /// No logs, the player only learns whether the transaction failed
+    #[default]
+    Hide,
+    /// All logs, but only for failed transactions
+    ErrorsOnly,
+    /// All logs
+    Full,
+    /// Only lines emitted while the given program, usually the player's, is executing
+    Program(Pubkey),
+}
+
+impl LogPolicy {
+    /// Picks the log lines to show for an execution
+    pub fn filter(&self, execution: &ExecutionMetadata) -> Vec<String> {
+        match self {
+            LogPolicy::Hide => vec![],
+            LogPolicy::ErrorsOnly if execution.result.is_ok() => vec![],
+            LogPolicy::ErrorsOnly | LogPolicy::Full => execution.logs.clone(),
+            LogPolicy::Program(program_id) => filter_program(&execution.logs, program_id),
+        }
+    }
+}
+
+/// Keeps the lines logged while `program_id` is at the top of the invoke stack
+fn filter_program(logs: &[String], program_id: &Pubkey) -> Vec<String> {
+    let program_id = program_id.to_string();
+    let mut stack: Vec<&str> = vec![];
+    let mut shown = vec![];
+
+    for line in logs {
+        let mut words = line.split(' ');
+        let (first, id, action) = (words.next(), words.next(), words.next());
+        // program output comes as `Program log: ..`, `Program data: ..` or `Program return: ..`,
+        // so requiring a pubkey keeps players from faking invoke and success lines
+        let id = id.filter(|id| Pubkey::from_str(id).is_ok());
+
+        match (first, id, action) {
+            (Some("Program"), Some(id), Some("invoke")) => {
+                stack.push(id);
+                if id == program_id {
+                    shown.push(line.clone());
+                }
+            }
+            (Some("Program"), Some(id), Some("success" | "failed:")) if stack.last() == Some(&id) => {
+                if id == program_id {
+                    shown.push(line.clone());
+                }
+                stack.pop();
+            }
+            _ => {
+                if stack.last() == Some(&program_id.as_str()) {
+                    shown.push(line.clone());
+                }
+            }
+        }
+    }
+
+    shown
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Sets what `run_player_ixs`, `run_player_ixs_v0` and `run_transaction` show of program logs
+    pub fn set_log_policy(&mut self, policy: LogPolicy) {
+        self.log_policy = policy;
+    }
+
+    /// Runs player supplied instructions, writing logs to the player as the `LogPolicy` allows
+    ///
+    /// The instructions are checked against the challenge's `TransactionLimits` first.
+    /// Logs are written as `log: [line]` lines, or one `{"logs": [..]}` object in JSON mode.
+    /// A failed transaction is returned as `ChallengeError::Transaction` after its logs are shown.
+    pub async fn run_player_ixs<T: Signers + ?Sized>(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &T,
+        payer: &Pubkey,
+    ) -> Result<ExecutionMetadata, ChallengeError> {
+        // counted before the limits add the ComputeBudget program on the player's behalf
+        let player_accounts = Message::new(ixs, Some(payer)).account_keys.len();
+        self.transaction_limits.check_accounts(player_accounts)?;
+
+        let (ixs, prepended) = self.transaction_limits.apply(ixs)?;
+        let mut tx = Transaction::new_with_payer(&ixs, Some(payer));
+
+        tx.try_sign(signers, self.ctx.last_blockhash)
+            .map_err(|e| ChallengeError::Protocol(format!("cannot sign transaction: {}", e)))?;
+        let mut execution = self.execute(tx.into(), self.transaction_limits.max_cpi_depth).await?;
+        hide_prepended(&mut execution, prepended);
+        self.show_logs(&execution).await?;
+        self.end_round().await?;
+        execution.check()?;
+
+        Ok(execution)
+    }
+
+    pub(crate) async fn show_logs(&mut self, execution: &ExecutionMetadata) -> Result<(), ChallengeError> {
+        let logs = self.log_policy.filter(execution);
+        if logs.is_empty() {
+            return Ok(());
+        }
+
+        match self.transport.protocol() {
+            Protocol::Text => {
+                for line in logs {
+                    self.transport.write_field("log", line).await?;
+                }
+            }
+            Protocol::Json => self.transport.write_json(&json!({ "logs": logs })).await?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn filter_program_follows_invoke_stack() {
+        let player = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let logs = logs(&[
+            &format!("Program {} invoke [1]", player),
+            "Program log: before",
+            &format!("Program {} invoke [2]", other),
+            "Program log: hidden",
+            &format!("Program {} success", other),
+            "Program log: after",
+            &format!("Program {} success", player),
+        ]);
+
+        assert_eq!(
+            filter_program(&logs, &player),
+            [&logs[0], &logs[1], &logs[5], &logs[6]].map(String::clone)
+        );
+    }
+
+    #[test]
+    fn filter_program_ignores_fake_structural_lines() {
+        let player = Pubkey::new_unique();
+        let logs = logs(&[
+            &format!("Program {} invoke [1]", player),
+            "Program log: invoke [2]",
+            "Program data: invoke",
+            "Program log: still shown",
+            "Program log: success",
+            "Program log: also shown",
+            &format!("Program {} failed: custom program error: 0x1", player),
+        ]);
+
+        assert_eq!(filter_program(&logs, &player), logs);
+    }
+}
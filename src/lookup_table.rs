@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::borrow::Cow;
+
+use solana_address_lookup_table_interface::{
+    program::ID as LOOKUP_TABLE_PROGRAM_ID,
+    state::{AddressLookupTable, LookupTableMeta, LOOKUP_TABLE_MAX_ADDRESSES},
+};
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    message::{v0, AddressLookupTableAccount, VersionedMessage},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    signer::signers::Signers,
+    transaction::VersionedTransaction,
+};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::limits::{account_count, hide_prepended};
+use crate::{Challenge, ChallengeBuilder, ChallengeError, ExecutionMetadata};
+
+/// Builds an active lookup table account, with every address usable right away
+fn lookup_table_account(rent: &Rent, authority: Option<Pubkey>, addresses: &[Pubkey]) -> Result<Account, ChallengeError> {
+    if addresses.len() > LOOKUP_TABLE_MAX_ADDRESSES {
+        return Err(ChallengeError::Setup(format!(
+            "lookup table holds at most {} addresses",
+            LOOKUP_TABLE_MAX_ADDRESSES
+        )));
+    }
+
+    let table = AddressLookupTable {
+        // never extended, so `last_extended_slot` stays 0 and every address is active from slot 1,
+        // where the bank processes transactions
+        meta: LookupTableMeta {
+            authority,
+            ..LookupTableMeta::default()
+        },
+        addresses: Cow::Borrowed(addresses),
+    };
+    let data = table
+        .serialize_for_tests()
+        .map_err(|e| ChallengeError::Setup(e.to_string()))?;
+
+    Ok(Account {
+        lamports: rent.minimum_balance(data.len()),
+        data,
+        owner: LOOKUP_TABLE_PROGRAM_ID,
+        executable: false,
+        rent_epoch: 0,
+    })
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> ChallengeBuilder<R, W> {
+    /// Adds an address lookup table to genesis
+    pub fn add_lookup_table(
+        &mut self,
+        address: Pubkey,
+        authority: Option<Pubkey>,
+        addresses: &[Pubkey],
+    ) -> Result<(), ChallengeError> {
+        let account = lookup_table_account(&Rent::default(), authority, addresses)?;
+        self.builder.add_account(address, account);
+
+        Ok(())
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Creates an address lookup table owned by `authority`, usable in the next transaction
+    pub async fn create_lookup_table(
+        &mut self,
+        authority: Option<Pubkey>,
+        addresses: &[Pubkey],
+    ) -> Result<Pubkey, ChallengeError> {
+        let table = Keypair::new().pubkey();
+        let rent = self.ctx.banks_client.get_rent().await?;
+        self.store_account(table, &lookup_table_account(&rent, authority, addresses)?.into())
+            .await?;
+
+        Ok(table)
+    }
+
+    /// Appends addresses to an existing lookup table, keeping its authority
+    pub async fn extend_lookup_table(&mut self, table: Pubkey, addresses: &[Pubkey]) -> Result<(), ChallengeError> {
+        let current = self.lookup_table(table).await?;
+
+        let rent = self.ctx.banks_client.get_rent().await?;
+        let mut all = current.addresses.to_vec();
+        all.extend_from_slice(addresses);
+
+        let mut account = lookup_table_account(&rent, current.meta.authority, &all)?;
+        account.lamports = account.lamports.max(self.get_account(&table).await?.lamports);
+        self.store_account(table, &account.into()).await?;
+
+        Ok(())
+    }
+
+    /// Reads a lookup table, in the form `v0::Message::try_compile` expects
+    pub async fn read_lookup_table(&mut self, table: Pubkey) -> Result<AddressLookupTableAccount, ChallengeError> {
+        Ok(AddressLookupTableAccount {
+            key: table,
+            addresses: self.lookup_table(table).await?.addresses.into_owned(),
+        })
+    }
+
+    pub(crate) async fn lookup_table(&mut self, table: Pubkey) -> Result<AddressLookupTable<'static>, ChallengeError> {
+        let account = self.get_account(&table).await?;
+        if account.owner != LOOKUP_TABLE_PROGRAM_ID {
+            return Err(ChallengeError::InvalidAccountData(format!("{} is not a lookup table", table)));
+        }
+
+        let parsed = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| ChallengeError::InvalidAccountData(e.to_string()))?;
+
+        Ok(AddressLookupTable {
+            meta: parsed.meta,
+            addresses: Cow::Owned(parsed.addresses.into_owned()),
+        })
+    }
+
+    /// Runs instructions as a v0 transaction, resolving accounts through `lookup_tables`
+    ///
+    /// Meant for setup instructions, use `run_player_ixs_v0` for those read with `read_instructions`
+    pub async fn run_ixs_v0<T: Signers + ?Sized>(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &T,
+        payer: &Pubkey,
+        lookup_tables: &[Pubkey],
+    ) -> Result<(), ChallengeError> {
+        let tables = self.read_lookup_tables(lookup_tables).await?;
+        let message = self.compile_v0(ixs, payer, &tables)?;
+        self.process_transaction(sign_v0(message, signers)?).await?;
+
+        Ok(())
+    }
+
+    /// Runs player supplied instructions as a v0 transaction, like `run_player_ixs` does for
+    /// legacy ones
+    ///
+    /// The instructions are checked against the challenge's `TransactionLimits` and logs are
+    /// shown to the player following its `LogPolicy`
+    pub async fn run_player_ixs_v0<T: Signers + ?Sized>(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &T,
+        payer: &Pubkey,
+        lookup_tables: &[Pubkey],
+    ) -> Result<ExecutionMetadata, ChallengeError> {
+        let tables = self.read_lookup_tables(lookup_tables).await?;
+
+        // counted before the limits add the ComputeBudget program on the player's behalf
+        let player_message = self.compile_v0(ixs, payer, &tables)?;
+        self.transaction_limits.check_accounts(account_count(&player_message))?;
+
+        let (ixs, prepended) = self.transaction_limits.apply(ixs)?;
+        let message = self.compile_v0(&ixs, payer, &tables)?;
+        let max_cpi_depth = self.transaction_limits.max_cpi_depth;
+        let mut execution = self.execute(sign_v0(message, signers)?, max_cpi_depth).await?;
+        hide_prepended(&mut execution, prepended);
+        self.show_logs(&execution).await?;
+        self.end_round().await?;
+        execution.check()?;
+
+        Ok(execution)
+    }
+
+    async fn read_lookup_tables(&mut self, tables: &[Pubkey]) -> Result<Vec<AddressLookupTableAccount>, ChallengeError> {
+        let mut accounts = Vec::with_capacity(tables.len());
+        for table in tables {
+            accounts.push(self.read_lookup_table(*table).await?);
+        }
+
+        Ok(accounts)
+    }
+
+    fn compile_v0(
+        &self,
+        ixs: &[Instruction],
+        payer: &Pubkey,
+        tables: &[AddressLookupTableAccount],
+    ) -> Result<VersionedMessage, ChallengeError> {
+        let message = v0::Message::try_compile(payer, ixs, tables, self.ctx.last_blockhash)
+            .map_err(|e| ChallengeError::Protocol(format!("cannot compile transaction: {}", e)))?;
+
+        Ok(VersionedMessage::V0(message))
+    }
+}
+
+fn sign_v0<T: Signers + ?Sized>(message: VersionedMessage, signers: &T) -> Result<VersionedTransaction, ChallengeError> {
+    VersionedTransaction::try_new(message, signers)
+        .map_err(|e| ChallengeError::Protocol(format!("cannot sign transaction: {}", e)))
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use bincode::Options;
+
+use solana_program::system_program;
+use solana_sdk::{
+    account::Account,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::VersionedTransaction,
+};
+
+use std::str::FromStr;
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::protocol::{decode_base64, JsonTransaction};
+use crate::{Challenge, ChallengeError, Protocol};
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Creates a keypair funded with `lamports` and hands its secret to the player
+    ///
+    /// The secret is written as `player keypair: [base58]`, the format `Keypair::from_base58_string` reads
+    pub async fn give_player_keypair(&mut self, lamports: u64) -> Result<Keypair, ChallengeError> {
+        let keypair = Keypair::new();
+        self.fund_player(keypair.pubkey(), lamports).await?;
+
+        self.transport.write_field("player keypair", keypair.to_base58_string()).await?;
+
+        Ok(keypair)
+    }
+
+    /// Reads a player generated pubkey and funds it with `lamports`
+    ///
+    /// The pubkey has to be fresh, so players can't have an existing account replaced
+    pub async fn read_player_pubkey(&mut self, lamports: u64) -> Result<Pubkey, ChallengeError> {
+        self.transport.prompt("player pubkey").await?;
+        let pubkey = Pubkey::from_str(self.transport.read_line().await?.trim())?;
+        if self.ctx.banks_client.get_account(pubkey).await?.is_some() {
+            return Err(ChallengeError::Protocol(format!("account {} already exists", pubkey)));
+        }
+
+        self.fund_player(pubkey, lamports).await?;
+
+        Ok(pubkey)
+    }
+
+    async fn fund_player(&mut self, pubkey: Pubkey, lamports: u64) -> Result<(), ChallengeError> {
+        self.store_account(pubkey, &Account::new(lamports, 0, &system_program::ID).into())
+            .await
+    }
+
+    /// Reads a transaction signed by the player
+    ///
+    /// A fresh blockhash is written as `blockhash: [hash]` first, which the player has to sign
+    /// over. The transaction is then read as one line of base64 encoded bincode, either a legacy
+    /// `Transaction` or a `VersionedTransaction`, or as a `protocol::JsonTransaction` in JSON mode.
+    ///
+    /// Transactions larger than a packet, malformed or with bad signatures are rejected
+    pub async fn read_transaction(&mut self) -> Result<VersionedTransaction, ChallengeError> {
+        self.ctx.last_blockhash = self.ctx.banks_client.get_latest_blockhash().await?;
+        self.transport.write_field("blockhash", self.ctx.last_blockhash).await?;
+
+        self.transport.prompt("transaction").await?;
+        let encoded = match self.transport.protocol() {
+            Protocol::Text => self.transport.read_line().await?.trim().to_string(),
+            Protocol::Json => self.transport.read_json::<JsonTransaction>().await?.transaction,
+        };
+
+        let bytes = decode_base64(&encoded)?;
+        if bytes.len() > PACKET_DATA_SIZE {
+            return Err(ChallengeError::Protocol(format!("transaction larger than {} bytes", PACKET_DATA_SIZE)));
+        }
+
+        let tx: VersionedTransaction = bincode::options()
+            .with_limit(PACKET_DATA_SIZE as u64)
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+            .deserialize(&bytes)
+            .map_err(|e| ChallengeError::Protocol(format!("invalid transaction: {}", e)))?;
+
+        tx.sanitize()
+            .map_err(|e| ChallengeError::Protocol(format!("invalid transaction: {}", e)))?;
+        if !tx.verify_with_results().into_iter().all(|ok| ok) {
+            return Err(ChallengeError::Protocol("invalid transaction signature".into()));
+        }
+
+        Ok(tx)
+    }
+
+    /// Processes a transaction as is, signatures included
+    ///
+    /// The transaction has to respect the challenge's `TransactionLimits`, and logs are shown
+    /// to the player following its `LogPolicy`
+    pub async fn run_transaction(&mut self, tx: VersionedTransaction) -> Result<(), ChallengeError> {
+        self.transaction_limits.check(&tx)?;
+
+        let execution = self.execute(tx, self.transaction_limits.max_cpi_depth).await?;
+        self.show_logs(&execution).await?;
+        self.end_round().await?;
+
+        execution.check()
+    }
+}
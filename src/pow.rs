@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: BSD-3-Clause
+//! Hashcash-style proof of work
+//!
+//! A solution is any string such that `sha256(prefix || solution)` starts with
+//! `difficulty` zero bits.
+
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// Checks `solution` against `prefix` at the given difficulty
+pub fn verify(prefix: &str, solution: &str, difficulty: u32) -> bool {
+    let mut hash = Sha256::default();
+    hash.update(prefix.as_bytes());
+    hash.update(solution.as_bytes());
+
+    leading_zero_bits(&hash.finalize()) >= difficulty
+}
+
+/// Brute forces a solution for `prefix`, meant for player-side clients
+pub fn solve(prefix: &str, difficulty: u32) -> String {
+    (0u64..)
+        .map(|nonce| nonce.to_string())
+        .find(|solution| verify(prefix, solution, difficulty))
+        .unwrap()
+}
+
+pub(crate) fn new_prefix() -> String {
+    rand::thread_rng()
+        .gen::<[u8; 16]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+
+    bits
+}
@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: BSD-3-Clause
+//! Wire formats for the JSON line protocol
+//!
+//! In JSON mode every message is a single line holding one JSON object. The server sends
+//! `{"prompt": "<name>"}` whenever it expects input, outputs as `{"<name>": <value>}` and
+//! errors as `{"error": "<message>"}`.
+
+use std::str::FromStr;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::ChallengeError;
+
+/// Player protocol spoken by a `ChallengeBuilder` and the `Challenge` built from it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Line based prompts with raw bytes for binary data, as expected by pwntools style scripts
+    #[default]
+    Text,
+    /// One JSON object per line, see the `protocol` module docs
+    Json,
+}
+
+/// Answer to the `program` prompt
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonProgram {
+    pub pubkey: String,
+    /// Base64 encoded ELF
+    pub program: String,
+}
+
+/// Answer to the `pow` prompt
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonProofOfWork {
+    pub solution: String,
+}
+
+/// Answer to the `transaction` prompt
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonTransaction {
+    /// Base64 encoded bincode `Transaction` or `VersionedTransaction`
+    pub transaction: String,
+}
+
+/// Answer to the `instruction` prompt
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonInstruction {
+    /// Defaults to the program the server asked an instruction for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_id: Option<String>,
+    pub accounts: Vec<JsonAccountMeta>,
+    /// Base64 encoded instruction data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Hex encoded instruction data, alternative to `data`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_hex: Option<String>,
+}
+
+/// Answer to the `anchor instruction` prompt, see `idl::encode_instruction`
+#[cfg(feature = "anchor")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonAnchorInstruction {
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    pub accounts: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonAccountMeta {
+    pub pubkey: String,
+    #[serde(default)]
+    pub signer: bool,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+impl JsonProgram {
+    pub(crate) fn decode(&self) -> Result<(Pubkey, Vec<u8>), ChallengeError> {
+        Ok((Pubkey::from_str(&self.pubkey)?, decode_base64(&self.program)?))
+    }
+}
+
+impl JsonInstruction {
+    /// Converts into an `Instruction`, rejecting a `program_id` other than `expected` if given
+    pub(crate) fn into_instruction(self, expected: Option<Pubkey>) -> Result<Instruction, ChallengeError> {
+        let program_id = match (self.program_id, expected) {
+            (Some(program_id), expected) => {
+                let program_id = Pubkey::from_str(&program_id)?;
+                if expected.is_some_and(|expected| expected != program_id) {
+                    return Err(ChallengeError::Protocol(format!("unexpected program id {}", program_id)));
+                }
+                program_id
+            }
+            (None, Some(expected)) => expected,
+            (None, None) => return Err(ChallengeError::Protocol("missing program_id".into())),
+        };
+
+        let data = match (self.data, self.data_hex) {
+            (Some(data), None) => decode_base64(&data)?,
+            (None, Some(data)) => hex::decode(data.trim_start_matches("0x"))
+                .map_err(|e| ChallengeError::Protocol(format!("invalid hex: {}", e)))?,
+            (None, None) => vec![],
+            (Some(_), Some(_)) => return Err(ChallengeError::Protocol("both data and data_hex given".into())),
+        };
+
+        let accounts = self
+            .accounts
+            .into_iter()
+            .map(|meta| {
+                Ok(AccountMeta {
+                    pubkey: Pubkey::from_str(&meta.pubkey)?,
+                    is_signer: meta.signer,
+                    is_writable: meta.writable,
+                })
+            })
+            .collect::<Result<_, ChallengeError>>()?;
+
+        Ok(Instruction { program_id, accounts, data })
+    }
+}
+
+pub(crate) fn decode_base64(data: &str) -> Result<Vec<u8>, ChallengeError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| ChallengeError::Protocol(format!("invalid base64: {}", e)))
+}
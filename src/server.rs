@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::Semaphore;
+
+use crate::error::INTERNAL_ERROR;
+use crate::transport::{self, ProtocolHandle};
+use crate::{ChallengeBuilder, ChallengeError, Protocol, TcpChallengeBuilder};
+
+/// Default cap on concurrently running sessions for `serve`
+pub const DEFAULT_MAX_SESSIONS: usize = 16;
+
+/// Accepts players on `addr` and runs `handler` once per connection
+///
+/// Equivalent to `serve_with_limit(addr, DEFAULT_MAX_SESSIONS, handler)`
+pub async fn serve<A, F, Fut>(addr: A, handler: F) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    F: Fn(TcpChallengeBuilder) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Box<dyn Error>>>,
+{
+    serve_with_limit(addr, DEFAULT_MAX_SESSIONS, handler).await
+}
+
+/// Accepts players on `addr` and runs `handler` once per connection, with at most
+/// `max_sessions` sessions alive at a time
+///
+/// Each session gets its own thread and runtime, so a player's bank never competes with
+/// the accept loop and handlers don't need to be `Send`. Connections beyond the limit
+/// wait in the listen backlog. Handler errors are logged to stderr and written to the player,
+/// using `ChallengeError::player_message` so internal failures aren't leaked; other errors
+/// are only reported as internal. A panicking handler only takes down its own session and
+/// is reported as an internal error.
+pub async fn serve_with_limit<A, F, Fut>(addr: A, max_sessions: usize, handler: F) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    F: Fn(TcpChallengeBuilder) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Box<dyn Error>>>,
+{
+    serve_listener(TcpListener::bind(addr).await?, max_sessions, handler).await
+}
+
+/// Like `serve_with_limit`, but accepts players on an already bound `listener`
+pub async fn serve_listener<F, Fut>(listener: TcpListener, max_sessions: usize, handler: F) -> io::Result<()>
+where
+    F: Fn(TcpChallengeBuilder) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Box<dyn Error>>>,
+{
+    let sessions = Arc::new(Semaphore::new(max_sessions));
+    let handler = Arc::new(handler);
+
+    loop {
+        let permit = sessions.clone().acquire_owned().await.expect("semaphore is never closed");
+
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(e) => {
+                eprintln!("accept error: {e}");
+                continue;
+            }
+        };
+
+        let handler = handler.clone();
+        // the permit moves into the session future, so it is only released once the session ends
+        if let Err(e) = spawn_session(socket, move |builder| async move {
+            let _permit = permit;
+            handler(builder).await
+        }) {
+            eprintln!("session setup error: {e}");
+        }
+    }
+}
+
+fn spawn_session<F, Fut>(socket: TcpStream, handler: F) -> io::Result<()>
+where
+    F: FnOnce(TcpChallengeBuilder) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), Box<dyn Error>>>,
+{
+    let peer = socket.peer_addr()?;
+
+    // the session runs on another runtime, so detach the socket from ours and keep a
+    // handle around for reporting errors once the builder has been consumed
+    let socket = socket.into_std()?;
+    let mut reporter = socket.try_clone()?;
+
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                eprintln!("[{peer}] runtime error: {e}");
+                return;
+            }
+        };
+
+        // shared with the builder, so errors follow the handler's `set_protocol` choice
+        let mut protocol: Option<ProtocolHandle> = None;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(async {
+                let builder = ChallengeBuilder::try_from(TcpStream::from_std(socket)?)?;
+                protocol = Some(builder.protocol_handle());
+                handler(builder).await
+            })
+        }));
+
+        let message = match result {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => {
+                eprintln!("[{peer}] session error: {e}");
+
+                Some(match e.downcast_ref::<ChallengeError>() {
+                    Some(e) => e.player_message(),
+                    None => INTERNAL_ERROR.to_string(),
+                })
+            }
+            // the panic message itself was already printed by the panic hook
+            Err(_) => {
+                eprintln!("[{peer}] session panicked");
+                Some(INTERNAL_ERROR.to_string())
+            }
+        };
+
+        if let Some(message) = message {
+            let protocol = protocol.map_or(Protocol::Text, |handle| handle.lock().map_or(Protocol::Text, |p| *p));
+
+            // best effort, the player may already be gone
+            let _ = reporter
+                .set_nonblocking(false)
+                .and_then(|_| writeln!(reporter, "{}", transport::error_line(protocol, &message)));
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::collections::HashMap;
+
+use solana_program::{bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable, loader_v4};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    native_loader,
+    pubkey::Pubkey,
+    sysvar,
+    transaction::VersionedTransaction,
+};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{Challenge, ChallengeError};
+
+/// Saved account states, taken with `Challenge::snapshot` or `Challenge::snapshot_touched`
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// `None` for accounts that didn't exist yet
+    accounts: HashMap<Pubkey, Option<Account>>,
+    /// Whether accounts first touched after the snapshot are rolled back too
+    touched: bool,
+}
+
+impl Snapshot {
+    /// Addresses covered by the snapshot
+    pub fn pubkeys(&self) -> impl Iterator<Item = &Pubkey> {
+        self.accounts.keys()
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Saves the current state of `pubkeys`
+    pub async fn snapshot(&mut self, pubkeys: &[Pubkey]) -> Result<Snapshot, ChallengeError> {
+        let mut accounts = HashMap::new();
+        for pubkey in pubkeys {
+            accounts.insert(*pubkey, self.ctx.banks_client.get_account(*pubkey).await?);
+        }
+
+        Ok(Snapshot { accounts, touched: false })
+    }
+
+    /// Saves the current state of every account the challenge has touched so far
+    ///
+    /// Touched accounts are those referenced by transactions run through `Challenge` or
+    /// written with its helpers, apart from sysvars and programs. Restoring the snapshot also
+    /// resets accounts first touched after it was taken. Writes made directly through `ctx`
+    /// are not tracked.
+    pub async fn snapshot_touched(&mut self) -> Result<Snapshot, ChallengeError> {
+        let pubkeys: Vec<Pubkey> = self.touched.keys().copied().collect();
+        let mut snapshot = self.snapshot(&pubkeys).await?;
+        snapshot.touched = true;
+
+        Ok(snapshot)
+    }
+
+    /// Puts the accounts in `snapshot` back the way they were, closing ones that didn't exist
+    ///
+    /// Also moves to a fresh blockhash, so a player can resend the transaction that got them here
+    pub async fn restore(&mut self, snapshot: &Snapshot) -> Result<(), ChallengeError> {
+        let mut states = snapshot.accounts.clone();
+        if snapshot.touched {
+            for (pubkey, first_seen) in &self.touched {
+                states.entry(*pubkey).or_insert_with(|| first_seen.clone());
+            }
+        }
+
+        for (pubkey, state) in states {
+            let account = state.map(AccountSharedData::from).unwrap_or_default();
+            self.ctx.set_account(&pubkey, &account);
+        }
+        self.ctx.get_new_latest_blockhash().await?;
+
+        Ok(())
+    }
+
+    /// Processes a transaction with preflight checks, tracking the accounts it references
+    pub(crate) async fn process_transaction(
+        &mut self,
+        tx: impl Into<VersionedTransaction>,
+    ) -> Result<(), ChallengeError> {
+        let tx = tx.into();
+        self.track_transaction(&tx).await?;
+        self.ctx.banks_client.process_transaction_with_preflight(tx).await?;
+
+        Ok(())
+    }
+
+    /// Overwrites an account, tracking it for `snapshot_touched`
+    pub(crate) async fn store_account(
+        &mut self,
+        pubkey: Pubkey,
+        account: &AccountSharedData,
+    ) -> Result<(), ChallengeError> {
+        self.track(&[pubkey]).await?;
+        self.ctx.set_account(&pubkey, account);
+
+        Ok(())
+    }
+
+    pub(crate) async fn track_transaction(&mut self, tx: &VersionedTransaction) -> Result<(), ChallengeError> {
+        let mut pubkeys = tx.message.static_account_keys().to_vec();
+        for lookup in tx.message.address_table_lookups().unwrap_or_default() {
+            // unresolvable lookups fail the transaction anyway
+            let Ok(table) = self.lookup_table(lookup.account_key).await else {
+                continue;
+            };
+            for index in lookup.writable_indexes.iter().chain(&lookup.readonly_indexes) {
+                pubkeys.extend(table.addresses.get(*index as usize));
+            }
+        }
+
+        self.track(&pubkeys).await
+    }
+
+    /// Remembers the state accounts had when first touched
+    ///
+    /// Sysvars and programs are left out, the bank keeps those consistent with its slot and
+    /// program cache, so writing old versions back would only break things
+    async fn track(&mut self, pubkeys: &[Pubkey]) -> Result<(), ChallengeError> {
+        for pubkey in pubkeys {
+            if self.touched.contains_key(pubkey) {
+                continue;
+            }
+
+            let account = self.ctx.banks_client.get_account(*pubkey).await?;
+            if account.as_ref().is_some_and(is_untracked) {
+                continue;
+            }
+            self.touched.insert(*pubkey, account);
+        }
+
+        Ok(())
+    }
+}
+
+/// Sysvars, programs and accounts owned by a loader, like upgradeable program data
+fn is_untracked(account: &Account) -> bool {
+    account.executable
+        || [
+            sysvar::ID,
+            native_loader::ID,
+            bpf_loader::ID,
+            bpf_loader_deprecated::ID,
+            bpf_loader_upgradeable::ID,
+            loader_v4::ID,
+        ]
+        .contains(&account.owner)
+}
@@ -0,0 +1,632 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use bytemuck::Pod;
+
+use solana_sdk::{program_pack::Pack, transaction::Transaction};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    pubkey::Pubkey,
+};
+
+use spl_associated_token_account_client::address::get_associated_token_address_with_program_id;
+use spl_associated_token_account_client::instruction::create_associated_token_account_idempotent;
+use spl_token_2022::extension::{
+    transfer_fee, transfer_hook, metadata_pointer, interest_bearing_mint, default_account_state,
+    cpi_guard::CpiGuard, immutable_owner::ImmutableOwner, memo_transfer::MemoTransfer,
+    BaseStateWithExtensions, BaseStateWithExtensionsMut, Extension, ExtensionType, StateWithExtensions,
+    StateWithExtensionsMut,
+};
+use spl_token_2022::instruction::AuthorityType;
+use spl_token_2022::state::AccountState;
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{Challenge, ChallengeBuilder, ChallengeError, Packed};
+
+/// Token-2022 mint extension, initialized by `Challenge::add_mint_2022`
+#[derive(Clone, Debug)]
+pub enum MintExtension {
+    TransferFee {
+        config_authority: Option<Pubkey>,
+        withdraw_authority: Option<Pubkey>,
+        basis_points: u16,
+        maximum_fee: u64,
+    },
+    TransferHook {
+        authority: Option<Pubkey>,
+        program_id: Option<Pubkey>,
+    },
+    PermanentDelegate {
+        delegate: Pubkey,
+    },
+    MetadataPointer {
+        authority: Option<Pubkey>,
+        metadata_address: Option<Pubkey>,
+    },
+    MintCloseAuthority {
+        authority: Option<Pubkey>,
+    },
+    InterestBearing {
+        rate_authority: Option<Pubkey>,
+        rate: i16,
+    },
+    DefaultAccountState {
+        state: AccountState,
+    },
+    NonTransferable,
+}
+
+impl MintExtension {
+    fn extension_type(&self) -> ExtensionType {
+        match self {
+            MintExtension::TransferFee { .. } => ExtensionType::TransferFeeConfig,
+            MintExtension::TransferHook { .. } => ExtensionType::TransferHook,
+            MintExtension::PermanentDelegate { .. } => ExtensionType::PermanentDelegate,
+            MintExtension::MetadataPointer { .. } => ExtensionType::MetadataPointer,
+            MintExtension::MintCloseAuthority { .. } => ExtensionType::MintCloseAuthority,
+            MintExtension::InterestBearing { .. } => ExtensionType::InterestBearingConfig,
+            MintExtension::DefaultAccountState { .. } => ExtensionType::DefaultAccountState,
+            MintExtension::NonTransferable => ExtensionType::NonTransferable,
+        }
+    }
+
+    /// Instruction initializing this extension, has to run before `InitializeMint`
+    fn instruction(&self, mint: &Pubkey) -> Result<Instruction, ProgramError> {
+        let program_id = &spl_token_2022::ID;
+
+        match self {
+            MintExtension::TransferFee { config_authority, withdraw_authority, basis_points, maximum_fee } => {
+                transfer_fee::instruction::initialize_transfer_fee_config(
+                    program_id,
+                    mint,
+                    config_authority.as_ref(),
+                    withdraw_authority.as_ref(),
+                    *basis_points,
+                    *maximum_fee,
+                )
+            }
+            MintExtension::TransferHook { authority, program_id: hook_program_id } => {
+                transfer_hook::instruction::initialize(program_id, mint, *authority, *hook_program_id)
+            }
+            MintExtension::PermanentDelegate { delegate } => {
+                spl_token_2022::instruction::initialize_permanent_delegate(program_id, mint, delegate)
+            }
+            MintExtension::MetadataPointer { authority, metadata_address } => {
+                metadata_pointer::instruction::initialize(program_id, mint, *authority, *metadata_address)
+            }
+            MintExtension::MintCloseAuthority { authority } => {
+                spl_token_2022::instruction::initialize_mint_close_authority(program_id, mint, authority.as_ref())
+            }
+            MintExtension::InterestBearing { rate_authority, rate } => {
+                interest_bearing_mint::instruction::initialize(program_id, mint, *rate_authority, *rate)
+            }
+            MintExtension::DefaultAccountState { state } => {
+                default_account_state::instruction::initialize_default_account_state(program_id, mint, state)
+            }
+            MintExtension::NonTransferable => {
+                spl_token_2022::instruction::initialize_non_transferable_mint(program_id, mint)
+            }
+        }
+    }
+}
+
+/// Token-2022 account extension, enabled by `Challenge::add_token_account_2022`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountExtension {
+    /// The account's owner can't be changed
+    ImmutableOwner,
+    /// Incoming transfers have to be preceded by a memo
+    MemoTransfer,
+    /// Restricts what the account's owner may sign for through CPI
+    CpiGuard,
+}
+
+impl AccountExtension {
+    fn extension_type(&self) -> ExtensionType {
+        match self {
+            AccountExtension::ImmutableOwner => ExtensionType::ImmutableOwner,
+            AccountExtension::MemoTransfer => ExtensionType::MemoTransfer,
+            AccountExtension::CpiGuard => ExtensionType::CpiGuard,
+        }
+    }
+
+    /// Turns this extension on in an initialized account
+    ///
+    /// `MemoTransfer` and `CpiGuard` are normally enabled by the owner, who may not be a signer here
+    fn enable(
+        &self,
+        account: &mut StateWithExtensionsMut<spl_token_2022::state::Account>,
+    ) -> Result<(), ProgramError> {
+        match self {
+            AccountExtension::ImmutableOwner => {}
+            AccountExtension::MemoTransfer => {
+                account.init_extension::<MemoTransfer>(true)?.require_incoming_transfer_memos = true.into();
+            }
+            AccountExtension::CpiGuard => {
+                account.init_extension::<CpiGuard>(true)?.lock_cpi = true.into();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mint configuration for `Challenge::create_mint`
+///
+/// Defaults to an SPL Token mint with 9 decimals, `ctx.payer` as mint authority,
+/// no freeze authority and a fresh random address
+pub struct MintBuilder {
+    decimals: u8,
+    mint_authority: Option<Option<Pubkey>>,
+    freeze_authority: Option<Pubkey>,
+    keypair: Option<Keypair>,
+    address: Option<Pubkey>,
+    token_program: Pubkey,
+    extensions: Vec<MintExtension>,
+    supply: Vec<(Pubkey, u64)>,
+}
+
+/// Result of `Challenge::create_mint`
+#[derive(Clone, Debug)]
+pub struct CreatedMint {
+    pub mint: Pubkey,
+    /// Token accounts holding the initial supply, in the order given to `MintBuilder::supply_to`
+    pub token_accounts: Vec<Pubkey>,
+}
+
+impl Default for MintBuilder {
+    fn default() -> Self {
+        MintBuilder {
+            decimals: 9,
+            mint_authority: None,
+            freeze_authority: None,
+            keypair: None,
+            address: None,
+            token_program: spl_token::ID,
+            extensions: vec![],
+            supply: vec![],
+        }
+    }
+}
+
+impl MintBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decimals(mut self, decimals: u8) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    /// Hands the mint authority to `authority` once the initial supply has been minted
+    pub fn mint_authority(mut self, authority: Pubkey) -> Self {
+        self.mint_authority = Some(Some(authority));
+        self
+    }
+
+    /// Removes the mint authority once the initial supply has been minted
+    pub fn fixed_supply(mut self) -> Self {
+        self.mint_authority = Some(None);
+        self
+    }
+
+    pub fn freeze_authority(mut self, authority: Pubkey) -> Self {
+        self.freeze_authority = Some(authority);
+        self
+    }
+
+    /// Creates the mint at the keypair's address
+    pub fn keypair(mut self, keypair: Keypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    /// Creates the mint at `address`, which doesn't need a known keypair, exclusive with `keypair`
+    pub fn address(mut self, address: Pubkey) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Creates a Token-2022 mint instead of an SPL Token one
+    pub fn token_2022(mut self) -> Self {
+        self.token_program = spl_token_2022::ID;
+        self
+    }
+
+    /// Enables a Token-2022 extension, requires `token_2022`
+    pub fn extension(mut self, extension: MintExtension) -> Self {
+        self.extensions.push(extension);
+        self
+    }
+
+    /// Mints `amount` tokens into a new token account owned by `owner`
+    pub fn supply_to(mut self, owner: Pubkey, amount: u64) -> Self {
+        self.supply.push((owner, amount));
+        self
+    }
+}
+
+/// Serializes an initialized token account the way its token program would
+///
+/// Token-2022 accounts get the `ImmutableOwner` extension, matching what the ATA program creates
+fn pack_token_account(
+    token_program: &Pubkey,
+    state: spl_token_2022::state::Account,
+) -> Result<Vec<u8>, ChallengeError> {
+    if *token_program == spl_token::ID {
+        let mut data = vec![0; spl_token_2022::state::Account::LEN];
+        spl_token_2022::state::Account::pack(state, &mut data)?;
+        return Ok(data);
+    }
+    if *token_program != spl_token_2022::ID {
+        return Err(ChallengeError::Setup(format!("{} is not a token program", token_program)));
+    }
+
+    let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[
+        ExtensionType::ImmutableOwner,
+    ])?;
+    let mut data = vec![0; space];
+    let mut account = StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(&mut data)?;
+    account.init_extension::<ImmutableOwner>(true)?;
+    account.base = state;
+    account.pack_base();
+    account.init_account_type()?;
+
+    Ok(data)
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> ChallengeBuilder<R, W> {
+    /// Adds an SPL Token mint with the given state to genesis
+    pub fn add_mint_account(&mut self, address: Pubkey, mint: spl_token::state::Mint) {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+
+        self.builder.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: spl_token::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    /// Adds an SPL Token account with the given state to genesis
+    ///
+    /// Delegates, frozen state and close authorities are kept as given. Native accounts
+    /// are funded with `amount` lamports on top of the rent exempt reserve.
+    pub fn add_token_account_state(&mut self, address: Pubkey, account: spl_token::state::Account) {
+        let native_amount = if account.is_native() { account.amount } else { 0 };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+
+        self.builder.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()) + native_amount,
+                data,
+                owner: spl_token::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    /// Adds `owner`'s associated token account for `mint` to genesis, holding `amount` tokens
+    ///
+    /// The mint itself isn't checked, so Token-2022 mints whose extensions require account
+    /// state (transfer fees, transfer hooks...) should use `Challenge::add_associated_token_account`
+    pub fn add_associated_token_account(
+        &mut self,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        token_program: &Pubkey,
+        amount: u64,
+    ) -> Result<Pubkey, ChallengeError> {
+        let address = get_associated_token_address_with_program_id(owner, mint, token_program);
+        let data = pack_token_account(
+            token_program,
+            spl_token_2022::state::Account {
+                mint: *mint,
+                owner: *owner,
+                amount,
+                delegate: COption::None,
+                state: AccountState::Initialized,
+                is_native: COption::None,
+                delegated_amount: 0,
+                close_authority: COption::None,
+            },
+        )?;
+
+        self.builder.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: *token_program,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+
+        Ok(address)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    pub async fn add_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Result<Pubkey, ChallengeError> {
+        let lamports = self
+            .ctx
+            .banks_client
+            .get_rent()
+            .await?
+            .minimum_balance(spl_token::state::Account::LEN);
+
+        let token_account_keypair = Keypair::new();
+        let token_account = token_account_keypair.pubkey();
+        let payer = &self.ctx.payer;
+        let mut tx = Transaction::new_with_payer(
+            &[
+                solana_program::system_instruction::create_account(
+                    &payer.pubkey(),
+                    &token_account,
+                    lamports,
+                    spl_token::state::Account::LEN.try_into().unwrap(),
+                    &spl_token::ID,
+                ),
+                spl_token::instruction::initialize_account(
+                    &spl_token::ID,
+                    &token_account,
+                    mint,
+                    owner
+                )?,
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&token_account_keypair, payer], self.ctx.last_blockhash);
+        self.process_transaction(tx).await?;
+
+        Ok(token_account)
+    }
+
+    /// Creates a Token-2022 account for `mint` with the given opt-in account extensions
+    ///
+    /// The account is also sized for whatever account extensions the mint requires
+    pub async fn add_token_account_2022(
+        &mut self,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        extensions: &[AccountExtension],
+    ) -> Result<Pubkey, ChallengeError> {
+        let mint_account = self.get_account(mint).await?;
+        let mint_extensions = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_account.data)?
+            .get_extension_types()?;
+        let mut extension_types = ExtensionType::get_required_init_account_extensions(&mint_extensions);
+        for extension_type in extensions.iter().map(AccountExtension::extension_type) {
+            if !extension_types.contains(&extension_type) {
+                extension_types.push(extension_type);
+            }
+        }
+        let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&extension_types)?;
+        let lamports = self.ctx.banks_client.get_rent().await?.minimum_balance(space);
+
+        let token_account_keypair = Keypair::new();
+        let token_account = token_account_keypair.pubkey();
+        let payer = &self.ctx.payer;
+        let mut ixs = vec![solana_program::system_instruction::create_account(
+            &payer.pubkey(),
+            &token_account,
+            lamports,
+            space as u64,
+            &spl_token_2022::ID,
+        )];
+        if extensions.contains(&AccountExtension::ImmutableOwner) {
+            ixs.push(spl_token_2022::instruction::initialize_immutable_owner(
+                &spl_token_2022::ID,
+                &token_account,
+            )?);
+        }
+        ixs.push(spl_token_2022::instruction::initialize_account(
+            &spl_token_2022::ID,
+            &token_account,
+            mint,
+            owner,
+        )?);
+        let mut tx = Transaction::new_with_payer(&ixs, Some(&payer.pubkey()));
+        tx.sign(&[&token_account_keypair, payer], self.ctx.last_blockhash);
+        self.process_transaction(tx).await?;
+
+        if extensions.iter().any(|extension| *extension != AccountExtension::ImmutableOwner) {
+            let mut account = self.get_account(&token_account).await?;
+            let mut state = StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack(&mut account.data)?;
+            for extension in extensions {
+                extension.enable(&mut state)?;
+            }
+            self.store_account(token_account, &account.into()).await?;
+        }
+
+        Ok(token_account)
+    }
+
+    /// Creates `owner`'s associated token account for `mint` through the ATA program
+    ///
+    /// Works for both token programs, and succeeds if the account already exists
+    pub async fn add_associated_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Result<Pubkey, ChallengeError> {
+        let token_program = self.get_account(mint).await?.owner;
+        let address = get_associated_token_address_with_program_id(owner, mint, &token_program);
+
+        // resending the same idempotent instruction in one slot would be rejected as a duplicate
+        if self.ctx.banks_client.get_account(address).await?.is_some() {
+            return Ok(address);
+        }
+
+        self.run_ix(create_associated_token_account_idempotent(
+            &self.ctx.payer.pubkey(),
+            owner,
+            mint,
+            &token_program,
+        ))
+        .await?;
+
+        Ok(address)
+    }
+
+    pub async fn add_mint(&mut self) -> Result<Pubkey, ChallengeError> {
+        Ok(self.create_mint(MintBuilder::new()).await?.mint)
+    }
+
+    /// Creates a Token-2022 mint with the given extensions, same authority and decimals as `add_mint`
+    pub async fn add_mint_2022(&mut self, extensions: &[MintExtension]) -> Result<Pubkey, ChallengeError> {
+        let builder = extensions
+            .iter()
+            .cloned()
+            .fold(MintBuilder::new().token_2022(), MintBuilder::extension);
+
+        Ok(self.create_mint(builder).await?.mint)
+    }
+
+    /// Creates a mint as configured by `mint`, then distributes its initial supply
+    ///
+    /// The mint is initialized with `ctx.payer` as authority so the supply can be minted,
+    /// and the authority is only handed over afterwards
+    pub async fn create_mint(&mut self, mint: MintBuilder) -> Result<CreatedMint, ChallengeError> {
+        let token_program = mint.token_program;
+        if !mint.extensions.is_empty() && token_program != spl_token_2022::ID {
+            return Err(ChallengeError::Setup("mint extensions require Token-2022".into()));
+        }
+
+        let extension_types: Vec<_> = mint.extensions.iter().map(MintExtension::extension_type).collect();
+        let space = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&extension_types)?;
+        let lamports = self.ctx.banks_client.get_rent().await?.minimum_balance(space);
+
+        let keypair = match (mint.keypair, mint.address) {
+            (Some(_), Some(_)) => return Err(ChallengeError::Setup("mint keypair and address are exclusive".into())),
+            (None, None) => Some(Keypair::new()),
+            (keypair, _) => keypair,
+        };
+        let payer = self.ctx.payer.pubkey();
+
+        let mut ixs = vec![];
+        let address = match &keypair {
+            Some(keypair) => {
+                ixs.push(solana_program::system_instruction::create_account(
+                    &payer,
+                    &keypair.pubkey(),
+                    lamports,
+                    space as u64,
+                    &token_program,
+                ));
+                keypair.pubkey()
+            }
+            None => {
+                // no keypair to sign a `create_account`, so allocate the account directly
+                let address = mint.address.unwrap();
+                self.store_account(
+                    address,
+                    &AccountSharedData::from(Account {
+                        lamports,
+                        data: vec![0; space],
+                        owner: token_program,
+                        executable: false,
+                        rent_epoch: 0,
+                    }),
+                )
+                .await?;
+                address
+            }
+        };
+
+        for extension in &mint.extensions {
+            ixs.push(extension.instruction(&address)?);
+        }
+        ixs.push(spl_token_2022::instruction::initialize_mint(
+            &token_program,
+            &address,
+            &payer,
+            mint.freeze_authority.as_ref(),
+            mint.decimals,
+        )?);
+
+        let mut tx = Transaction::new_with_payer(&ixs, Some(&payer));
+        match &keypair {
+            Some(keypair) => tx.sign(&[keypair, &self.ctx.payer], self.ctx.last_blockhash),
+            None => tx.sign(&[&self.ctx.payer], self.ctx.last_blockhash),
+        }
+        self.process_transaction(tx).await?;
+
+        let mut token_accounts = vec![];
+        for (owner, amount) in mint.supply {
+            let token_account = if token_program == spl_token_2022::ID {
+                self.add_token_account_2022(&address, &owner, &[]).await?
+            } else {
+                self.add_token_account(&address, &owner).await?
+            };
+            self.mint_to(amount, &address, &token_account).await?;
+            token_accounts.push(token_account);
+        }
+
+        if let Some(authority) = mint.mint_authority {
+            self.run_ix(spl_token_2022::instruction::set_authority(
+                &token_program,
+                &address,
+                authority.as_ref(),
+                AuthorityType::MintTokens,
+                &payer,
+                &[],
+            )?)
+            .await?;
+        }
+
+        Ok(CreatedMint { mint: address, token_accounts })
+    }
+
+    /// Mints `amount` tokens to `account`, works for both token programs
+    pub async fn mint_to(
+        &mut self,
+        amount: u64,
+        mint: &Pubkey,
+        account: &Pubkey,
+    ) -> Result<(), ChallengeError> {
+        let token_program = self.get_account(mint).await?.owner;
+
+        self.run_ix(
+            spl_token_2022::instruction::mint_to(
+                &token_program,
+                mint,
+                account,
+                &self.ctx.payer.pubkey(),
+                &[],
+                amount,
+            )?,
+        )
+        .await
+    }
+
+    pub async fn read_token_account(&mut self, pubkey: Pubkey) -> Result<spl_token::state::Account, ChallengeError> {
+        Ok(self.read_account::<Packed<spl_token::state::Account>>(pubkey).await?.0)
+    }
+
+    /// Reads the base state of a Token-2022 account, ignoring its extensions
+    pub async fn read_token_account_2022(&mut self, pubkey: Pubkey) -> Result<spl_token_2022::state::Account, ChallengeError> {
+        let account = self.get_account(&pubkey).await?;
+        Ok(StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)?.base)
+    }
+
+    /// Reads extension `E` of a Token-2022 mint
+    pub async fn read_mint_extension<E: Extension + Pod>(&mut self, mint: Pubkey) -> Result<E, ChallengeError> {
+        let account = self.get_account(&mint).await?;
+        Ok(*StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data)?.get_extension::<E>()?)
+    }
+
+    /// Reads extension `E` of a Token-2022 account
+    pub async fn read_token_account_extension<E: Extension + Pod>(&mut self, pubkey: Pubkey) -> Result<E, ChallengeError> {
+        let account = self.get_account(&pubkey).await?;
+        Ok(*StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)?.get_extension::<E>()?)
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::io;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout_at, Instant};
+
+use crate::{ChallengeError, Protocol};
+
+/// Longest line accepted by `Transport::read_line`, larger inputs ask for an explicit limit
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Protocol of a session, shared so errors can be reported after the transport is gone
+pub(crate) type ProtocolHandle = Arc<Mutex<Protocol>>;
+
+/// Player connection shared by `ChallengeBuilder` and `Challenge`
+pub(crate) struct Transport<R, W> {
+    input: R,
+    output: W,
+    read_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    protocol: ProtocolHandle,
+}
+
+/// Formats an error line for the player in the given protocol
+pub(crate) fn error_line(protocol: Protocol, message: &str) -> String {
+    match protocol {
+        Protocol::Text => format!("error: {}", message),
+        Protocol::Json => json!({ "error": message }).to_string(),
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Transport<R, W> {
+    pub(crate) fn new(input: R, output: W) -> Self {
+        Transport {
+            input,
+            output,
+            read_timeout: None,
+            deadline: None,
+            protocol: ProtocolHandle::default(),
+        }
+    }
+
+    pub(crate) fn protocol(&self) -> Protocol {
+        *self.protocol.lock().unwrap()
+    }
+
+    pub(crate) fn set_protocol(&mut self, protocol: Protocol) {
+        *self.protocol.lock().unwrap() = protocol;
+    }
+
+    pub(crate) fn protocol_handle(&self) -> ProtocolHandle {
+        self.protocol.clone()
+    }
+
+    pub(crate) fn set_read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = Some(timeout);
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Reads a single line, without the trailing newline
+    pub(crate) async fn read_line(&mut self) -> Result<String, ChallengeError> {
+        self.read_line_limited(MAX_LINE_LEN).await
+    }
+
+    /// Reads a single line of at most `max_len` bytes, failing before buffering anything longer
+    ///
+    /// The read timeout restarts whenever data arrives, so only an idle player runs into it
+    pub(crate) async fn read_line_limited(&mut self, max_len: usize) -> Result<String, ChallengeError> {
+        let mut line = vec![];
+        loop {
+            let cutoff = self.cutoff(true);
+            let available = limited(cutoff, self.input.fill_buf()).await?;
+            if available.is_empty() {
+                break;
+            }
+
+            let (used, done) = match available.iter().position(|b| *b == b'\n') {
+                Some(newline) => (newline + 1, true),
+                None => (available.len(), false),
+            };
+            line.extend_from_slice(&available[..used]);
+            self.input.consume(used);
+
+            if done || line.len() > max_len {
+                break;
+            }
+        }
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.len() > max_len {
+            return Err(ChallengeError::Protocol(format!("line longer than {} bytes", max_len)));
+        }
+
+        String::from_utf8(line).map_err(|_| ChallengeError::Protocol("line is not valid utf-8".into()))
+    }
+
+    /// Fills `buf`, with the read timeout restarting whenever data arrives
+    pub(crate) async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let cutoff = self.cutoff(true);
+            let read = limited(cutoff, self.input.read(&mut buf[filled..])).await?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            filled += read;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a line and flushes, so prompts reach the player before we block on input
+    pub(crate) async fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let cutoff = self.cutoff(false);
+        limited(cutoff, async {
+            self.output.write_all(line.as_bytes()).await?;
+            self.output.write_all(b"\n").await?;
+            self.output.flush().await
+        })
+        .await
+    }
+
+    /// Asks the player for `name`, as `name: ` or `{"prompt": name}`
+    pub(crate) async fn prompt(&mut self, name: &str) -> io::Result<()> {
+        match self.protocol() {
+            Protocol::Text => self.write_line(&format!("{}: ", name)).await,
+            Protocol::Json => self.write_json(&json!({ "prompt": name })).await,
+        }
+    }
+
+    /// Sends a named value, as `name: value` or `{name: "value"}`
+    pub(crate) async fn write_field(&mut self, name: &str, value: impl Display) -> io::Result<()> {
+        match self.protocol() {
+            Protocol::Text => self.write_line(&format!("{}: {}", name, value)).await,
+            Protocol::Json => self.write_json(&json!({ name: value.to_string() })).await,
+        }
+    }
+
+    /// Sends free-form text, wrapped as `{"message": line}` in JSON mode
+    pub(crate) async fn write_message(&mut self, line: &str) -> io::Result<()> {
+        match self.protocol() {
+            Protocol::Text => self.write_line(line).await,
+            Protocol::Json => self.write_json(&json!({ "message": line })).await,
+        }
+    }
+
+    pub(crate) async fn write_json(&mut self, value: &Value) -> io::Result<()> {
+        self.write_line(&value.to_string()).await
+    }
+
+    /// Reads a line holding a single JSON value
+    pub(crate) async fn read_json<T: DeserializeOwned>(&mut self) -> Result<T, ChallengeError> {
+        self.read_json_limited(MAX_LINE_LEN).await
+    }
+
+    /// Reads a line of at most `max_len` bytes holding a single JSON value
+    pub(crate) async fn read_json_limited<T: DeserializeOwned>(&mut self, max_len: usize) -> Result<T, ChallengeError> {
+        let line = self.read_line_limited(max_len).await?;
+        serde_json::from_str(&line).map_err(|e| ChallengeError::Protocol(format!("invalid json: {}", e)))
+    }
+
+    /// Earliest instant the next operation has to finish by, along with the reason to report
+    fn cutoff(&self, is_read: bool) -> Option<(Instant, &'static str)> {
+        let idle = self
+            .read_timeout
+            .filter(|_| is_read)
+            .map(|timeout| (Instant::now() + timeout, "read timed out"));
+        let session = self.deadline.map(|deadline| (deadline, "session deadline exceeded"));
+
+        match (idle, session) {
+            (Some(idle), Some(session)) => Some(std::cmp::min_by_key(idle, session, |c| c.0)),
+            (idle, session) => idle.or(session),
+        }
+    }
+}
+
+async fn limited<T>(
+    cutoff: Option<(Instant, &'static str)>,
+    fut: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match cutoff {
+        Some((at, reason)) => timeout_at(at, fut)
+            .await
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, reason))),
+        None => fut.await,
+    }
+}
@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use solana_loader_v3_interface::{get_program_data_address, state::UpgradeableLoaderState};
+use solana_program::bpf_loader_upgradeable;
+use solana_sdk::{
+    account::Account,
+    clock::Slot,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signer,
+};
+
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+use crate::{helpers, Challenge, ChallengeBuilder, ChallengeError};
+
+/// Program-data account of an upgradeable program, see `Challenge::read_program_data`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramData {
+    pub address: Pubkey,
+    /// Slot of the last deploy or upgrade
+    pub slot: Slot,
+    /// `None` once the program has been made immutable
+    pub upgrade_authority: Option<Pubkey>,
+    pub elf: Vec<u8>,
+}
+
+fn loader_account(data: Vec<u8>, executable: bool) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()).max(1),
+        data,
+        owner: bpf_loader_upgradeable::ID,
+        executable,
+        rent_epoch: 0,
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> ChallengeBuilder<R, W> {
+    /// Like `add_program`, but deploys under BPF Loader Upgradeable
+    pub fn add_upgradeable_program(
+        &mut self,
+        path: &str,
+        key: Option<Pubkey>,
+        upgrade_authority: Option<Pubkey>,
+        programdata_address: Option<Pubkey>,
+    ) -> Option<Pubkey> {
+        let program_so = std::fs::read(path).unwrap();
+
+        self.add_upgradeable_program_bytes(&program_so, key, upgrade_authority, programdata_address)
+    }
+
+    /// Like `add_program_bytes`, but deploys under BPF Loader Upgradeable
+    ///
+    /// `upgrade_authority` may be any key, the player's included, while `None` deploys the
+    /// program as immutable. The program-data account lives at `programdata_address`, or the
+    /// usual derived address if `None`; the loader only checks it against the program's state.
+    pub fn add_upgradeable_program_bytes(
+        &mut self,
+        program_so: &[u8],
+        key: Option<Pubkey>,
+        upgrade_authority: Option<Pubkey>,
+        programdata_address: Option<Pubkey>,
+    ) -> Option<Pubkey> {
+        let program_key = key.unwrap_or(helpers::keypair_from_data(program_so).pubkey());
+
+        // Prevent duplicate program IDs
+        if !self.added_programs.insert(program_key) {
+            return None;
+        }
+
+        let programdata_address = programdata_address.unwrap_or_else(|| get_program_data_address(&program_key));
+
+        let program = bincode::serialize(&UpgradeableLoaderState::Program { programdata_address })
+            .expect("loader state serializes");
+
+        let mut programdata = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: upgrade_authority,
+        })
+        .expect("loader state serializes");
+        programdata.resize(UpgradeableLoaderState::size_of_programdata_metadata(), 0);
+        programdata.extend_from_slice(program_so);
+
+        self.builder.add_account(program_key, loader_account(program, true));
+        self.builder.add_account(programdata_address, loader_account(programdata, false));
+
+        Some(program_key)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Challenge<R, W> {
+    /// Reads the program-data account of an upgradeable program
+    pub async fn read_program_data(&mut self, program_id: Pubkey) -> Result<ProgramData, ChallengeError> {
+        let invalid = || ChallengeError::InvalidAccountData(format!("{} is not an upgradeable program", program_id));
+
+        let program = self.get_account(&program_id).await?;
+        if program.owner != bpf_loader_upgradeable::ID {
+            return Err(invalid());
+        }
+        let address = match bincode::deserialize(&program.data) {
+            Ok(UpgradeableLoaderState::Program { programdata_address }) => programdata_address,
+            _ => return Err(invalid()),
+        };
+
+        let programdata = self.get_account(&address).await?;
+        let (slot, upgrade_authority) = match bincode::deserialize(&programdata.data) {
+            Ok(UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address }) => {
+                (slot, upgrade_authority_address)
+            }
+            _ => return Err(invalid()),
+        };
+        let elf = programdata
+            .data
+            .get(UpgradeableLoaderState::size_of_programdata_metadata()..)
+            .unwrap_or_default()
+            .to_vec();
+
+        Ok(ProgramData { address, slot, upgrade_authority, elf })
+    }
+}
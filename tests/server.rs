@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: BSD-3-Clause
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use sol_ctf_framework::serve_listener;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, Instant};
+
+/// Polls `cond` until it holds, failing the test after a generous deadline
+async fn wait_until(what: &str, cond: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !cond() {
+        assert!(Instant::now() < deadline, "timed out waiting until {}", what);
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sessions_beyond_limit_wait() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let started = Arc::new(AtomicUsize::new(0));
+    // sessions block until the test hands out a permit
+    let release = Arc::new(Semaphore::new(0));
+
+    let counters = (started.clone(), release.clone());
+    tokio::spawn(serve_listener(listener, 1, move |_builder| {
+        let (started, release) = counters.clone();
+        async move {
+            started.fetch_add(1, Ordering::SeqCst);
+            release.acquire().await?.forget();
+            Ok(())
+        }
+    }));
+
+    let _first = TcpStream::connect(addr).await.unwrap();
+    let _second = TcpStream::connect(addr).await.unwrap();
+    wait_until("the first session started", || started.load(Ordering::SeqCst) >= 1).await;
+
+    // the second session may only start once the first one ends
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(started.load(Ordering::SeqCst), 1, "second session started before the first ended");
+
+    release.add_permits(1);
+    wait_until("the second session started", || started.load(Ordering::SeqCst) == 2).await;
+    release.add_permits(1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn internal_errors_are_not_leaked() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(serve_listener(listener, 1, |_builder| async {
+        Err(std::fs::read_to_string("/nonexistent/flag.txt").unwrap_err().into())
+    }));
+
+    let mut line = String::new();
+    let stream = TcpStream::connect(addr).await.unwrap();
+    BufReader::new(stream).read_line(&mut line).await.unwrap();
+    assert_eq!(line.trim_end(), "error: internal error, please contact admin");
+}